@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS outbox_attempts;
+DROP TABLE IF EXISTS outbox_messages;
@@ -0,0 +1,23 @@
+CREATE TABLE outbox_messages (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    payload JSONB NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX outbox_messages_status_next_attempt_at_idx ON outbox_messages (status, next_attempt_at);
+
+SELECT diesel_manage_updated_at('outbox_messages');
+
+CREATE TABLE outbox_attempts (
+    id SERIAL PRIMARY KEY,
+    message_id UUID NOT NULL REFERENCES outbox_messages (id) ON DELETE CASCADE,
+    succeeded BOOLEAN NOT NULL,
+    error VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX outbox_attempts_message_id_idx ON outbox_attempts (message_id);
@@ -0,0 +1 @@
+UPDATE outbox_messages SET status = 'failed' WHERE status = 'dead_letter';
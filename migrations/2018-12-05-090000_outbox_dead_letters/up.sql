@@ -0,0 +1 @@
+UPDATE outbox_messages SET status = 'dead_letter' WHERE status = 'failed';
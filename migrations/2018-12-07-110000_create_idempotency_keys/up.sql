@@ -0,0 +1,7 @@
+CREATE TABLE idempotency_keys (
+    key VARCHAR NOT NULL,
+    scope VARCHAR NOT NULL,
+    message_id UUID NOT NULL REFERENCES outbox_messages (id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (key, scope)
+);
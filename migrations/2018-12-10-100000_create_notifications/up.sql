@@ -0,0 +1,18 @@
+CREATE TABLE notifications (
+    id UUID PRIMARY KEY,
+    recipient VARCHAR NOT NULL,
+    template VARCHAR,
+    subject VARCHAR NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'queued',
+    provider_message_id VARCHAR,
+    user_id INTEGER,
+    sent_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX notifications_recipient_idx ON notifications (recipient);
+CREATE INDEX notifications_created_at_idx ON notifications (created_at);
+CREATE INDEX notifications_provider_message_id_idx ON notifications (provider_message_id);
+
+SELECT diesel_manage_updated_at('notifications');
@@ -0,0 +1,2 @@
+ALTER TABLE notifications DROP COLUMN IF EXISTS provider;
+ALTER TABLE outbox_attempts DROP COLUMN IF EXISTS provider;
@@ -0,0 +1,2 @@
+ALTER TABLE outbox_attempts ADD COLUMN provider VARCHAR;
+ALTER TABLE notifications ADD COLUMN provider VARCHAR;
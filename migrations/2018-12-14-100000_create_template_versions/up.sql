@@ -0,0 +1,12 @@
+CREATE TABLE template_versions (
+    id SERIAL PRIMARY KEY,
+    template_id INTEGER NOT NULL REFERENCES templates (id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    data VARCHAR NOT NULL,
+    author_id INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT template_versions_template_id_version_key UNIQUE (template_id, version)
+);
+
+INSERT INTO template_versions (template_id, version, data)
+SELECT id, 1, data FROM templates;
@@ -0,0 +1,6 @@
+DELETE FROM templates WHERE locale <> 'en';
+
+ALTER TABLE templates DROP CONSTRAINT templates_name_locale_key;
+ALTER TABLE templates ADD CONSTRAINT unique_name UNIQUE (name);
+
+ALTER TABLE templates DROP COLUMN locale;
@@ -0,0 +1,4 @@
+ALTER TABLE templates ADD COLUMN locale VARCHAR NOT NULL DEFAULT 'en';
+
+ALTER TABLE templates DROP CONSTRAINT unique_name;
+ALTER TABLE templates ADD CONSTRAINT templates_name_locale_key UNIQUE (name, locale);
@@ -0,0 +1,2 @@
+ALTER TABLE templates ADD COLUMN subject VARCHAR;
+ALTER TABLE template_versions ADD COLUMN subject VARCHAR;
@@ -0,0 +1,2 @@
+ALTER TABLE templates ADD COLUMN plain_text VARCHAR;
+ALTER TABLE template_versions ADD COLUMN plain_text VARCHAR;
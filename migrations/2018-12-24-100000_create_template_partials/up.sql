@@ -0,0 +1,23 @@
+CREATE TABLE template_partials (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    data VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT template_partials_name_key UNIQUE (name)
+);
+
+SELECT diesel_manage_updated_at('template_partials');
+
+-- base layout, templates use it as {{#> layout}}{{#*inline "title"}}...{{/inline}}...{{/layout}}
+INSERT INTO template_partials (name, data) VALUES
+('layout', '<html>
+  <head>
+    <meta charset="utf-8">
+    <title>{{#> title}}Storiqa{{/title}}</title>
+  </head>
+  <body>
+    {{> @partial-block}}
+  </body>
+</html>
+');
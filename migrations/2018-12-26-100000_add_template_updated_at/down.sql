@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS set_updated_at ON templates;
+ALTER TABLE templates DROP COLUMN IF EXISTS updated_at;
@@ -0,0 +1,3 @@
+ALTER TABLE templates ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp;
+
+SELECT diesel_manage_updated_at('templates');
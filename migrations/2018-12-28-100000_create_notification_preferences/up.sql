@@ -0,0 +1,14 @@
+CREATE TABLE notification_preferences (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    email VARCHAR NOT NULL,
+    category VARCHAR NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT notification_preferences_email_category_key UNIQUE (email, category)
+);
+
+CREATE INDEX notification_preferences_user_id_idx ON notification_preferences (user_id);
+
+SELECT diesel_manage_updated_at('notification_preferences');
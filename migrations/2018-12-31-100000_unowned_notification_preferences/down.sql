@@ -0,0 +1,2 @@
+DELETE FROM notification_preferences WHERE user_id IS NULL;
+ALTER TABLE notification_preferences ALTER COLUMN user_id SET NOT NULL;
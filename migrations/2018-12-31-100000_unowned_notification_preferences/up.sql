@@ -0,0 +1,2 @@
+-- preferences recorded by unsubscribe links have no owner until a user claims the address
+ALTER TABLE notification_preferences ALTER COLUMN user_id DROP NOT NULL;
@@ -0,0 +1,8 @@
+CREATE TABLE suppressions (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL,
+    reason VARCHAR NOT NULL,
+    source VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT suppressions_email_key UNIQUE (email)
+);
@@ -0,0 +1,12 @@
+CREATE TABLE notification_events (
+    id SERIAL PRIMARY KEY,
+    notification_id UUID NOT NULL REFERENCES notifications (id) ON DELETE CASCADE,
+    status VARCHAR NOT NULL,
+    provider_event_id VARCHAR,
+    occurred_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT notification_events_provider_event_id_key UNIQUE (provider_event_id)
+);
+
+CREATE INDEX notification_events_notification_id_idx ON notification_events (notification_id);
+CREATE INDEX notification_events_occurred_at_idx ON notification_events (occurred_at);
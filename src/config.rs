@@ -1,6 +1,7 @@
 //! Config module contains the top-level config for the app.
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
 
 use stq_http;
 use stq_logging::GrayLogConfig;
@@ -11,12 +12,24 @@ use serde::Deserialize;
 
 use config_crate::{Config as RawConfig, ConfigError, Environment, File};
 
+/// Environment variable with the key of unsubscribe tokens
+const UNSUBSCRIBE_SECRET_VAR: &'static str = "STQ_NOTIF_UNSUBSCRIBE_SECRET";
+
 /// Basic settings - HTTP binding address and database DSN
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub server: Server,
     pub client: Client,
     pub sendgrid: SendGridConf,
+    pub smtp: Option<SmtpConf>,
+    pub mail: MailConf,
+    pub outbox: OutboxConf,
+    pub scheduler: SchedulerConf,
+    pub idempotency: IdempotencyConf,
+    pub templates: TemplatesConf,
+    pub attachments: AttachmentsConf,
+    pub unsubscribe: UnsubscribeConf,
+    pub users: UsersConf,
     pub graylog: Option<GrayLogConfig>,
     pub sentry: Option<SentryConfig>,
     pub emarsys: Option<EmarsysConf>,
@@ -41,7 +54,7 @@ pub struct Client {
     pub http_timeout_ms: u64,
 }
 
-/// Smtp client settings
+/// SendGrid api settings
 #[derive(Debug, Deserialize, Clone)]
 pub struct SendGridConf {
     pub api_addr: String,
@@ -49,6 +62,118 @@ pub struct SendGridConf {
     pub send_mail_path: String,
     pub from_email: String,
     pub from_name: String,
+    pub retry: RetryConf,
+    /// Base64 encoded public key of the signed event webhook, events are refused while it's not set
+    pub webhook_public_key: Option<String>,
+}
+
+/// Smtp client settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct SmtpConf {
+    pub host: String,
+    pub port: u16,
+    pub starttls: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub retry: RetryConf,
+}
+
+/// Email provider used to deliver messages
+#[derive(Debug, Deserialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailProvider {
+    Sendgrid,
+    Smtp,
+}
+
+impl MailProvider {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            MailProvider::Sendgrid => "sendgrid",
+            MailProvider::Smtp => "smtp",
+        }
+    }
+}
+
+impl fmt::Display for MailProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Email delivery settings. Providers are tried in the listed order, a provider
+/// failing `failure_threshold` times in a row is skipped for `cooldown_ms`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MailConf {
+    pub providers: Vec<MailProvider>,
+    pub failure_threshold: u32,
+    pub cooldown_ms: u64,
+    /// Addresses getting a blind copy of every moderation email
+    #[serde(default)]
+    pub moderation_bcc: Vec<String>,
+    /// Recipients accepted by a single batch send
+    pub batch_max_recipients: usize,
+}
+
+/// Delivery retry policy of the email provider
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetryConf {
+    pub max_attempts: i32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub backoff_multiplier: f64,
+    pub jitter: f64,
+}
+
+/// Outbox worker settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutboxConf {
+    pub poll_interval_ms: u64,
+    pub batch_size: i64,
+    pub lease_ms: u64,
+}
+
+/// Scheduled emails settings, `send_at` can't be later than `max_delay_days` from now
+#[derive(Debug, Deserialize, Clone)]
+pub struct SchedulerConf {
+    pub poll_interval_ms: u64,
+    pub max_delay_days: u64,
+}
+
+/// Idempotency settings of the send endpoints
+#[derive(Debug, Deserialize, Clone)]
+pub struct IdempotencyConf {
+    pub window_secs: u64,
+}
+
+/// Templates settings, `default_locale` is used when no template matches the requested locale
+#[derive(Debug, Deserialize, Clone)]
+pub struct TemplatesConf {
+    pub default_locale: String,
+    /// Fail rendering if a template refers to a variable missing in the email data
+    pub strict_mode: bool,
+}
+
+/// Limits of the attachments of one email, sizes are of the decoded content
+#[derive(Debug, Deserialize, Clone)]
+pub struct AttachmentsConf {
+    pub max_count: usize,
+    pub max_size_bytes: usize,
+    pub max_total_size_bytes: usize,
+}
+
+/// Unsubscribe links settings, `url` is the public address of the service
+#[derive(Debug, Deserialize, Clone)]
+pub struct UnsubscribeConf {
+    pub url: String,
+    /// Key of the token signatures, set with `STQ_NOTIF_UNSUBSCRIBE_SECRET` outside of development
+    pub secret: String,
+}
+
+/// Users microservice, the accounts and their addresses are stored there
+#[derive(Debug, Deserialize, Clone)]
+pub struct UsersConf {
+    pub url: String,
 }
 
 /// Emarsys api settings
@@ -122,7 +247,29 @@ impl Config {
         // Add in settings from the environment (with a prefix of STQ_USERS)
         s.merge(Environment::with_prefix("STQ_NOTIF"))?;
 
-        s.try_into()
+        // Secrets are kept out of the config files
+        if let Ok(secret) = env::var(UNSUBSCRIBE_SECRET_VAR) {
+            s.set("unsubscribe.secret", secret)?;
+        }
+
+        let config: Self = s.try_into()?;
+        if config.unsubscribe.secret.is_empty() {
+            return Err(ConfigError::Message(format!("{} must not be empty", UNSUBSCRIBE_SECRET_VAR)));
+        }
+        Ok(config)
+    }
+
+    /// Primary email provider, the first one in the failover chain
+    pub fn mail_provider(&self) -> MailProvider {
+        self.mail.providers.first().cloned().unwrap_or(MailProvider::Sendgrid)
+    }
+
+    /// Retry policy of the email provider
+    pub fn mail_retry_policy(&self, provider: MailProvider) -> RetryConf {
+        match provider {
+            MailProvider::Sendgrid => self.sendgrid.retry.clone(),
+            MailProvider::Smtp => self.smtp.as_ref().expect("Smtp config not found").retry.clone(),
+        }
     }
 
     pub fn to_http_config(&self) -> stq_http::client::Config {
@@ -15,7 +15,9 @@ use super::routes::*;
 use config::Config;
 use repos::repo_factory::*;
 use services::emarsys::EmarsysClient;
-use services::sendgrid::SendgridService;
+use services::template_registry::TemplateRegistry;
+use services::transport_chain::TransportChain;
+use services::users::UsersClient;
 
 /// Static context for all app
 pub struct StaticContext<T, M, F>
@@ -31,7 +33,9 @@ where
     pub client_handle: ClientHandle,
     pub repo_factory: F,
     pub emarsys_client: Arc<EmarsysClient>,
-    pub sendgrid_service: Arc<SendgridService>,
+    pub mail_transports: Arc<TransportChain>,
+    pub users_client: Arc<UsersClient>,
+    pub template_registry: Arc<TemplateRegistry>,
 }
 
 impl<
@@ -48,9 +52,11 @@ impl<
         config: Arc<Config>,
         repo_factory: F,
         emarsys_client: Arc<EmarsysClient>,
-        sendgrid_service: Arc<SendgridService>,
+        mail_transports: Arc<TransportChain>,
+        users_client: Arc<UsersClient>,
     ) -> Self {
         let route_parser = Arc::new(create_route_parser());
+        let template_registry = Arc::new(TemplateRegistry::new(config.templates.strict_mode));
         Self {
             route_parser,
             db_pool,
@@ -59,7 +65,9 @@ impl<
             config,
             repo_factory,
             emarsys_client,
-            sendgrid_service,
+            mail_transports,
+            users_client,
+            template_registry,
         }
     }
 }
@@ -79,7 +87,9 @@ impl<
             config: self.config.clone(),
             repo_factory: self.repo_factory.clone(),
             emarsys_client: self.emarsys_client.clone(),
-            sendgrid_service: self.sendgrid_service.clone(),
+            mail_transports: self.mail_transports.clone(),
+            users_client: self.users_client.clone(),
+            template_registry: self.template_registry.clone(),
         }
     }
 }
@@ -88,14 +98,30 @@ impl<
 pub struct DynamicContext {
     pub user_id: Option<UserId>,
     pub correlation_token: String,
+    pub idempotency_key: Option<String>,
+    /// Locales requested by the client, most preferred first
+    pub locales: Vec<String>,
 }
 
 impl DynamicContext {
     /// Create a new dynamic context for each request
-    pub fn new(user_id: Option<UserId>, correlation_token: String) -> Self {
+    pub fn new(user_id: Option<UserId>, correlation_token: String, idempotency_key: Option<String>, locales: Vec<String>) -> Self {
         Self {
             user_id,
             correlation_token,
+            idempotency_key,
+            locales,
         }
     }
+
+    /// Key for deduplicating send requests, falls back to the correlation token
+    pub fn request_idempotency_key(&self) -> Option<String> {
+        self.idempotency_key.clone().or_else(|| {
+            if self.correlation_token.is_empty() {
+                None
+            } else {
+                Some(self.correlation_token.clone())
+            }
+        })
+    }
 }
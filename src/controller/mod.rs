@@ -1,13 +1,21 @@
 pub mod context;
 pub mod routes;
 
-use std::str::FromStr;
+use std::cmp::Ordering;
+use std::str::{self, FromStr};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
 
 use diesel::{connection::AnsiTransactionManager, pg::Pg, Connection};
 use failure::Fail;
 use futures::future;
 use futures::prelude::*;
-use hyper::{header::Authorization, server::Request, Delete, Get, Post, Put};
+use hyper::{
+    header::{AcceptLanguage, Authorization},
+    server::Request,
+    Delete, Get, Post, Put,
+};
 use r2d2::ManageConnection;
 
 use stq_http::{
@@ -25,11 +33,24 @@ use models;
 use repos::repo_factory::*;
 use sentry_integration::log_and_capture_error;
 use services::emarsys::EmarsysService;
-use services::mail::{MailService, SimpleMailService};
+use services::mail::{BatchMailService, MailService, SimpleMailService, TemplateMailService};
+use services::notification_preferences::NotificationPreferencesService;
+use services::notifications::NotificationsService;
+use services::outbox::OutboxService;
+use services::scheduler::SchedulerService;
+use services::sendgrid_events::{self, SendGridEventsService, SignedEvents};
+use services::suppressions::SuppressionsService;
 use services::templates::TemplatesService;
+use services::unsubscribe::UnsubscribeService;
 use services::user_roles::UserRolesService;
 use services::Service;
 
+/// Header used by upstream services to deduplicate retried send requests
+const IDEMPOTENCY_KEY_HEADER: &'static str = "Idempotency-Key";
+
+/// Page size for list endpoints when `count` is not provided
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
 /// Controller handles route parsing and calling `Service` layer
 pub struct ControllerImpl<T, M, F>
 where
@@ -62,7 +83,9 @@ impl<
     fn call(&self, req: Request) -> ControllerFuture {
         let user_id = get_user_id(&req);
         let correlation_token = request_util::get_correlation_token(&req);
-        let dynamic_context = DynamicContext::new(user_id, correlation_token);
+        let idempotency_key = get_idempotency_key(&req);
+        let locales = get_locales(&req);
+        let dynamic_context = DynamicContext::new(user_id, correlation_token, idempotency_key, locales);
         let service = Service::new(self.static_context.clone(), dynamic_context);
 
         let path = req.path().to_string();
@@ -82,38 +105,184 @@ impl<
             ),
             // POST /simple-mail
             (&Post, Some(Route::SimpleMail)) => serialize_future(
-                parse_body::<SimpleMail>(req.body())
-                    .map_err(|e| e.context("Parsing body failed, target: SimpleMail").context(Error::Parse).into())
+                parse_body::<models::SimpleMailPayload>(req.body())
+                    .map_err(|e| e.context("Parsing body failed, target: SimpleMailPayload").context(Error::Parse).into())
                     .and_then(move |mail| service.send_mail(mail)),
             ),
             // POST /users/order-update-state
             (&Post, Some(Route::OrderUpdateStateForUser)) => serialize_future(
-                parse_body::<OrderUpdateStateForUser>(req.body())
+                parse_body::<models::LocalizedMail<OrderUpdateStateForUser>>(req.body())
                     .map_err(|e| {
                         e.context("Parsing body failed, target: OrderUpdateStateForUser")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |mail| service.send_email_with_template(TemplateVariant::OrderUpdateStateForUser, mail)),
+                    }).and_then(move |payload| service.send_email_with_template(TemplateVariant::OrderUpdateStateForUser, payload)),
+            ),
+            // GET /templates
+            (&Get, Some(Route::Templates)) => {
+                let name = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "name" => String
+                );
+                serialize_future(service.list_templates(name))
+            }
+            // POST /templates
+            (&Post, Some(Route::Templates)) => serialize_future(
+                parse_body::<models::CreateTemplate>(req.body())
+                    .map_err(|e| e.context("Parsing body failed, target: CreateTemplate").context(Error::Parse).into())
+                    .and_then(move |payload| service.create_template(payload)),
             ),
             // GET /templates/<template_name>
-            (&Get, Some(Route::Templates { template })) => serialize_future(service.get_template_by_name(template)),
+            (&Get, Some(Route::Template { template })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                match locale {
+                    Some(locale) => serialize_future(service.get_localized_template(template, locale)),
+                    None => serialize_future(service.get_template_by_name(template)),
+                }
+            }
+            // DELETE /templates/<template_name>
+            (&Delete, Some(Route::Template { template })) => serialize_future(service.delete_template(template)),
             // PUT /templates/<template_name>
-            (&Put, Some(Route::Templates { template })) => serialize_future(
+            (&Put, Some(Route::Template { template })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(
+                    read_body(req.body())
+                        .map_err(|e| {
+                            e.context("Parsing body failed, target: UpdateTemplate")
+                                .context(Error::Parse)
+                                .into()
+                        }).and_then(move |text| match locale {
+                            Some(locale) => service.update_localized_template(template, locale, text),
+                            None => service.update_template(template, text),
+                        }),
+                )
+            }
+            // GET /templates/<template_name>/subject
+            (&Get, Some(Route::TemplateSubject { template })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(service.get_template_subject(template, locale))
+            }
+            // PUT /templates/<template_name>/subject
+            (&Put, Some(Route::TemplateSubject { template })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(
+                    read_body(req.body())
+                        .map_err(|e| {
+                            e.context("Parsing body failed, target: UpdateTemplateSubject")
+                                .context(Error::Parse)
+                                .into()
+                        }).and_then(move |subject| service.update_template_subject(template, locale, subject)),
+                )
+            }
+            // GET /templates/<template_name>/plain-text
+            (&Get, Some(Route::TemplatePlainText { template })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(service.get_template_plain_text(template, locale))
+            }
+            // PUT /templates/<template_name>/plain-text
+            (&Put, Some(Route::TemplatePlainText { template })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(
+                    read_body(req.body())
+                        .map_err(|e| {
+                            e.context("Parsing body failed, target: UpdateTemplatePlainText")
+                                .context(Error::Parse)
+                                .into()
+                        }).and_then(move |text| service.update_template_plain_text(template, locale, text)),
+                )
+            }
+            // POST /templates/<template_name>/preview
+            (&Post, Some(Route::TemplatePreview { template })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(
+                    parse_body::<models::TemplatePreview>(req.body())
+                        .map_err(|e| {
+                            e.context("Parsing body failed, target: TemplatePreview")
+                                .context(Error::Parse)
+                                .into()
+                        }).and_then(move |preview| service.preview_template(template, locale, preview)),
+                )
+            }
+            // GET /templates/<template_name>/versions
+            (&Get, Some(Route::TemplateVersions { template })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(service.list_template_versions(template, locale))
+            }
+            // GET /templates/<template_name>/versions/<version>
+            (&Get, Some(Route::TemplateVersion { template, version })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(service.get_template_version(template, locale, version))
+            }
+            // POST /templates/<template_name>/versions/<version>/rollback
+            (&Post, Some(Route::TemplateVersionRollback { template, version })) => {
+                let locale = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "locale" => String
+                );
+                serialize_future(service.rollback_template(template, locale, version))
+            }
+            // POST /send/<template_name>
+            (&Post, Some(Route::SendTemplate { template })) => serialize_future(
+                parse_body::<models::TemplateMail>(req.body())
+                    .map_err(|e| e.context("Parsing body failed, target: TemplateMail").context(Error::Parse).into())
+                    .and_then(move |mail| service.send_template_mail(template, mail)),
+            ),
+            // POST /batch/<template_name>
+            (&Post, Some(Route::SendBatch { template })) => serialize_future(
+                parse_body::<models::BatchMail>(req.body())
+                    .map_err(|e| e.context("Parsing body failed, target: BatchMail").context(Error::Parse).into())
+                    .and_then(move |batch| service.send_batch_mail(template, batch)),
+            ),
+            // GET /template-partials
+            (&Get, Some(Route::TemplatePartials)) => serialize_future(service.list_template_partials()),
+            // GET /template-partials/<name>
+            (&Get, Some(Route::TemplatePartial { name })) => serialize_future(service.get_template_partial(name)),
+            // PUT /template-partials/<name>
+            (&Put, Some(Route::TemplatePartial { name })) => serialize_future(
                 read_body(req.body())
                     .map_err(|e| {
-                        e.context("Parsing body failed, target: UpdateTemplate")
+                        e.context("Parsing body failed, target: UpdateTemplatePartial")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |text| service.update_template(template, text)),
+                    }).and_then(move |data| service.update_template_partial(name, data)),
             ),
+            // DELETE /template-partials/<name>
+            (&Delete, Some(Route::TemplatePartial { name })) => serialize_future(service.delete_template_partial(name)),
             // POST /stores/order-update-state
             (&Post, Some(Route::OrderUpdateStateForStore)) => serialize_future(
-                parse_body::<OrderUpdateStateForStore>(req.body())
+                parse_body::<models::LocalizedMail<OrderUpdateStateForStore>>(req.body())
                     .map_err(|e| {
                         e.context("Parsing body failed, target: OrderUpdateStateForStore")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |mail| service.send_email_with_template(TemplateVariant::OrderUpdateStateForStore, mail)),
+                    }).and_then(move |payload| service.send_email_with_template(TemplateVariant::OrderUpdateStateForStore, payload)),
             ),
             // POST /users/email-verification
             (&Post, Some(Route::EmailVerificationForUser)) => {
@@ -127,31 +296,31 @@ impl<
                 };
 
                 serialize_future(
-                    parse_body::<EmailVerificationForUser>(req.body())
+                    parse_body::<models::LocalizedMail<EmailVerificationForUser>>(req.body())
                         .map_err(|e| {
                             e.context("Parsing body failed, target: EmailVerificationForUser")
                                 .context(Error::Parse)
                                 .into()
-                        }).and_then(move |mail| service.send_email_with_template(variant, mail)),
+                        }).and_then(move |payload| service.send_email_with_template(variant, payload)),
                 )
             },
             // POST /stores/order-create
             (&Post, Some(Route::OrderCreateForStore)) => serialize_future(
-                parse_body::<OrderCreateForStore>(req.body())
+                parse_body::<models::LocalizedMail<OrderCreateForStore>>(req.body())
                     .map_err(|e| {
                         e.context("Parsing body failed, target: OrderCreateForStore")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |mail| service.send_email_with_template(TemplateVariant::OrderCreateForStore, mail)),
+                    }).and_then(move |payload| service.send_email_with_template(TemplateVariant::OrderCreateForStore, payload)),
             ),
             // POST /users/order-create
             (&Post, Some(Route::OrderCreateForUser)) => serialize_future(
-                parse_body::<OrderCreateForUser>(req.body())
+                parse_body::<models::LocalizedMail<OrderCreateForUser>>(req.body())
                     .map_err(|e| {
                         e.context("Parsing body failed, target: OrderCreateForUser")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |mail| service.send_email_with_template(TemplateVariant::OrderCreateForUser, mail)),
+                    }).and_then(move |payload| service.send_email_with_template(TemplateVariant::OrderCreateForUser, payload)),
             ),
             // POST /users/apply-email-verification
             (&Post, Some(Route::ApplyEmailVerificationForUser)) => {
@@ -165,12 +334,12 @@ impl<
                 };
 
                 serialize_future(
-                    parse_body::<ApplyEmailVerificationForUser>(req.body())
+                    parse_body::<models::LocalizedMail<ApplyEmailVerificationForUser>>(req.body())
                         .map_err(|e| {
                             e.context("Parsing body failed, target: ApplyEmailVerificationForUser")
                                 .context(Error::Parse)
                                 .into()
-                        }).and_then(move |mail| service.send_email_with_template(variant, mail)),
+                        }).and_then(move |payload| service.send_email_with_template(variant, payload)),
                 )
             }
             // POST /users/password-reset
@@ -186,50 +355,50 @@ impl<
 
 
                 serialize_future(
-                    parse_body::<PasswordResetForUser>(req.body())
+                    parse_body::<models::LocalizedMail<PasswordResetForUser>>(req.body())
                         .map_err(|e| {
                             e.context("Parsing body failed, target: PasswordResetForUser")
                                 .context(Error::Parse)
                                 .into()
-                        }).and_then(move |mail| service.send_email_with_template(variant, mail)),
+                        }).and_then(move |payload| service.send_email_with_template(variant, payload)),
                 )
             }
             ,
             // POST /users/stores/update-moderation-status
             (&Post, Some(Route::StoreModerationStatusForUser)) => serialize_future(
-                parse_body::<StoreModerationStatusForUser>(req.body())
+                parse_body::<models::LocalizedMail<StoreModerationStatusForUser>>(req.body())
                     .map_err(|e| {
                         e.context("Parsing body failed, target: StoreModerationStatusForUser")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |mail| service.send_email_with_template(TemplateVariant::StoreModerationStatusForUser, mail)),
+                    }).and_then(move |payload| service.send_email_with_template(TemplateVariant::StoreModerationStatusForUser, payload)),
             ),
             // POST /users/base_products/update-moderation-status
             (&Post, Some(Route::BaseProductModerationStatusForUser)) => serialize_future(
-                parse_body::<BaseProductModerationStatusForUser>(req.body())
+                parse_body::<models::LocalizedMail<BaseProductModerationStatusForUser>>(req.body())
                     .map_err(|e| {
                         e.context("Parsing body failed, target: BaseProductModerationStatusForUser")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |mail| service.send_email_with_template(TemplateVariant::BaseProductModerationStatusForUser, mail)),
+                    }).and_then(move |payload| service.send_email_with_template(TemplateVariant::BaseProductModerationStatusForUser, payload)),
             ),
             // POST /moderators/stores/update-moderation-status
             (&Post, Some(Route::StoreModerationStatusForModerator)) => serialize_future(
-                parse_body::<StoreModerationStatusForModerator>(req.body())
+                parse_body::<models::LocalizedMail<StoreModerationStatusForModerator>>(req.body())
                     .map_err(|e| {
                         e.context("Parsing body failed, target: StoreModerationStatusForModerator")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |mail| service.send_email_with_template(TemplateVariant::StoreModerationStatusForModerator, mail)),
+                    }).and_then(move |payload| service.send_email_with_template(TemplateVariant::StoreModerationStatusForModerator, payload)),
             ),
             // POST /moderators/base_products/update-moderation-status
             (&Post, Some(Route::BaseProductModerationStatusForModerator)) => serialize_future(
-                parse_body::<BaseProductModerationStatusForModerator>(req.body())
+                parse_body::<models::LocalizedMail<BaseProductModerationStatusForModerator>>(req.body())
                     .map_err(|e| {
                         e.context("Parsing body failed, target: BaseProductModerationStatusForModerator")
                             .context(Error::Parse)
                             .into()
-                    }).and_then(move |mail| service.send_email_with_template(TemplateVariant::BaseProductModerationStatusForModerator, mail)),
+                    }).and_then(move |payload| service.send_email_with_template(TemplateVariant::BaseProductModerationStatusForModerator, payload)),
             ),
             (&Post, Some(Route::ApplyPasswordResetForUser)) => {
                 let project = parse_query!(
@@ -243,12 +412,12 @@ impl<
 
 
                 serialize_future(
-                    parse_body::<ApplyPasswordResetForUser>(req.body())
+                    parse_body::<models::LocalizedMail<ApplyPasswordResetForUser>>(req.body())
                         .map_err(|e| {
                             e.context("Parsing body failed, target: ApplyPasswordResetForUser")
                                 .context(Error::Parse)
                                 .into()
-                        }).and_then(move |mail| service.send_email_with_template(variant, mail)),
+                        }).and_then(move |payload| service.send_email_with_template(variant, payload)),
                 )
             }
             ,
@@ -261,6 +430,109 @@ impl<
             }
             (Delete, Some(Route::RolesByUserId { user_id })) => serialize_future({ service.delete_user_role_by_user_id(user_id) }),
             (Delete, Some(Route::RoleById { id })) => serialize_future({ service.delete_user_role_by_id(id) }),
+            // GET /dead-letters
+            (&Get, Some(Route::DeadLetters)) => {
+                let (offset, count) = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "offset" => i64,
+                    "count" => i64
+                );
+                serialize_future(service.list_dead_letters(offset.unwrap_or(0), count.unwrap_or(DEFAULT_PAGE_SIZE)))
+            }
+            // GET /dead-letters/<id>
+            (&Get, Some(Route::DeadLetter { id })) => serialize_future(service.get_outbox_message(id)),
+            // POST /dead-letters/<id>/requeue
+            (&Post, Some(Route::DeadLetterRequeue { id })) => serialize_future(service.requeue_dead_letter(id)),
+            // DELETE /scheduled/<id>
+            (&Delete, Some(Route::ScheduledEmail { id })) => serialize_future(service.cancel_scheduled_email(id)),
+            // GET /notifications
+            (&Get, Some(Route::Notifications)) => {
+                let (recipient, template, status, provider, from, to, offset, count) = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "recipient" => String,
+                    "template" => String,
+                    "status" => models::NotificationStatus,
+                    "provider" => String,
+                    "from" => DateTime<Utc>,
+                    "to" => DateTime<Utc>,
+                    "offset" => i64,
+                    "count" => i64
+                );
+                let search = models::NotificationsSearch {
+                    recipient,
+                    template,
+                    status,
+                    provider,
+                    created_from: from.map(SystemTime::from),
+                    created_to: to.map(SystemTime::from),
+                };
+                serialize_future(service.search_notifications(search, offset.unwrap_or(0), count.unwrap_or(DEFAULT_PAGE_SIZE)))
+            }
+            // GET /notifications/<id>
+            (&Get, Some(Route::Notification { id })) => serialize_future(service.get_notification(id)),
+            // GET /reports/engagement
+            (&Get, Some(Route::EngagementReport)) => {
+                let (template, from, to) = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "template" => String,
+                    "from" => DateTime<Utc>,
+                    "to" => DateTime<Utc>
+                );
+                let search = models::EngagementSearch {
+                    template,
+                    sent_from: from.map(SystemTime::from),
+                    sent_to: to.map(SystemTime::from),
+                };
+                serialize_future(service.engagement_report(search))
+            }
+            // GET /notification-preferences
+            (&Get, Some(Route::NotificationPreferences)) => serialize_future(service.list_notification_preferences()),
+            // PUT /notification-preferences
+            (&Put, Some(Route::NotificationPreferences)) => serialize_future(
+                parse_body::<models::UpdateNotificationPreference>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: UpdateNotificationPreference")
+                            .context(Error::Parse)
+                            .into()
+                    }).and_then(move |payload| service.update_notification_preference(payload)),
+            ),
+            // DELETE /notification-preferences/<id>
+            (&Delete, Some(Route::NotificationPreference { id })) => serialize_future(service.delete_notification_preference(id)),
+            // GET /unsubscribe/<token>, only shows the opt-out to confirm
+            (&Get, Some(Route::Unsubscribe { token })) => serialize_future(service.unsubscribe_confirmation(token)),
+            // POST /unsubscribe/<token>, sent on confirmation and by mail clients supporting one-click unsubscribe
+            (&Post, Some(Route::Unsubscribe { token })) => serialize_future(service.unsubscribe(token)),
+            // GET /suppressions
+            (&Get, Some(Route::Suppressions)) => {
+                let (email, offset, count) = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "email" => String,
+                    "offset" => i64,
+                    "count" => i64
+                );
+                serialize_future(service.list_suppressions(email, offset.unwrap_or(0), count.unwrap_or(DEFAULT_PAGE_SIZE)))
+            }
+            // POST /suppressions
+            (&Post, Some(Route::Suppressions)) => serialize_future(
+                parse_body::<models::CreateSuppression>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: CreateSuppression")
+                            .context(Error::Parse)
+                            .into()
+                    }).and_then(move |payload| service.add_suppression(payload)),
+            ),
+            // DELETE /suppressions/<email>
+            (&Delete, Some(Route::Suppression { email })) => serialize_future(service.delete_suppression(email)),
+            // POST /webhooks/sendgrid/events
+            (&Post, Some(Route::SendGridEvents)) => {
+                let signature = get_header(&req, sendgrid_events::SIGNATURE_HEADER);
+                let timestamp = get_header(&req, sendgrid_events::TIMESTAMP_HEADER);
+                serialize_future(
+                    read_body(req.body())
+                        .map_err(|e| e.context("Parsing body failed, target: SendGrid events").context(Error::Parse).into())
+                        .and_then(move |body| service.ingest_sendgrid_events(SignedEvents { signature, timestamp, body })),
+                )
+            }
 
             // Fallback
             (m, _) => Box::new(future::err(
@@ -287,3 +559,32 @@ fn get_user_id(req: &Request) -> Option<UserId> {
         .and_then(|id| i32::from_str(&id).ok())
         .map(UserId)
 }
+
+fn get_idempotency_key(req: &Request) -> Option<String> {
+    get_header(req, IDEMPOTENCY_KEY_HEADER)
+}
+
+fn get_header(req: &Request, name: &str) -> Option<String> {
+    req.headers()
+        .get_raw(name)
+        .and_then(|raw| raw.one())
+        .and_then(|value| str::from_utf8(value).ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Locales from the `locale` query parameter and the `Accept-Language` header, most preferred first
+fn get_locales(req: &Request) -> Vec<String> {
+    let locale = parse_query!(
+        req.query().unwrap_or_default(),
+        "locale" => String
+    );
+
+    let mut languages = req.headers().get::<AcceptLanguage>().map(|header| header.0.clone()).unwrap_or_default();
+    languages.sort_by(|a, b| b.quality.partial_cmp(&a.quality).unwrap_or(Ordering::Equal));
+
+    locale
+        .into_iter()
+        .chain(languages.into_iter().map(|language| language.item.to_string()))
+        .collect()
+}
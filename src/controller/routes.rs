@@ -1,8 +1,7 @@
-use std::str::FromStr;
-
+use percent_encoding::percent_decode;
 use stq_router::RouteParser;
-use stq_static_resources::TemplateVariant;
 use stq_types::*;
+use uuid::Uuid;
 
 /// List of all routes with params for the app
 #[derive(Clone, Debug, PartialEq)]
@@ -23,8 +22,32 @@ pub enum Route {
     Roles,
     RoleById { id: RoleId },
     RolesByUserId { user_id: UserId },
-    Templates { template: TemplateVariant },
+    Templates,
+    Template { template: String },
+    TemplateSubject { template: String },
+    TemplatePlainText { template: String },
+    TemplatePreview { template: String },
+    TemplateVersions { template: String },
+    TemplateVersion { template: String, version: i32 },
+    TemplateVersionRollback { template: String, version: i32 },
+    SendTemplate { template: String },
+    SendBatch { template: String },
+    TemplatePartials,
+    TemplatePartial { name: String },
     EmarsysContact,
+    DeadLetters,
+    DeadLetter { id: Uuid },
+    DeadLetterRequeue { id: Uuid },
+    ScheduledEmail { id: Uuid },
+    Notifications,
+    Notification { id: Uuid },
+    NotificationPreferences,
+    NotificationPreference { id: i32 },
+    Unsubscribe { token: String },
+    Suppressions,
+    Suppression { email: String },
+    SendGridEvents,
+    EngagementReport,
 }
 
 pub fn create_route_parser() -> RouteParser<Route> {
@@ -76,12 +99,161 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .map(|id| Route::RoleById { id })
     });
 
-    router.add_route_with_params(r"^/templates/([a-zA-Z-_]+)$", |params| {
+    router.add_route(r"^/templates$", || Route::Templates);
+
+    router.add_route_with_params(r"^/templates/([a-zA-Z0-9-_]+)$", |params| {
+        params.get(0).map(|string_template| Route::Template {
+            template: string_template.to_string(),
+        })
+    });
+
+    router.add_route_with_params(r"^/templates/([a-zA-Z0-9-_]+)/subject$", |params| {
+        params.get(0).map(|string_template| Route::TemplateSubject {
+            template: string_template.to_string(),
+        })
+    });
+
+    router.add_route_with_params(r"^/templates/([a-zA-Z0-9-_]+)/plain-text$", |params| {
+        params.get(0).map(|string_template| Route::TemplatePlainText {
+            template: string_template.to_string(),
+        })
+    });
+
+    router.add_route_with_params(r"^/templates/([a-zA-Z0-9-_]+)/preview$", |params| {
+        params.get(0).map(|string_template| Route::TemplatePreview {
+            template: string_template.to_string(),
+        })
+    });
+
+    router.add_route_with_params(r"^/templates/([a-zA-Z0-9-_]+)/versions$", |params| {
+        params.get(0).map(|string_template| Route::TemplateVersions {
+            template: string_template.to_string(),
+        })
+    });
+
+    router.add_route_with_params(r"^/templates/([a-zA-Z0-9-_]+)/versions/(\d+)$", |params| {
+        if let (Some(string_template), Some(string_version)) = (params.get(0), params.get(1)) {
+            if let Ok(version) = string_version.parse() {
+                return Some(Route::TemplateVersion {
+                    template: string_template.to_string(),
+                    version,
+                });
+            }
+        }
+        None
+    });
+
+    router.add_route_with_params(r"^/templates/([a-zA-Z0-9-_]+)/versions/(\d+)/rollback$", |params| {
+        if let (Some(string_template), Some(string_version)) = (params.get(0), params.get(1)) {
+            if let Ok(version) = string_version.parse() {
+                return Some(Route::TemplateVersionRollback {
+                    template: string_template.to_string(),
+                    version,
+                });
+            }
+        }
+        None
+    });
+
+    router.add_route_with_params(r"^/send/([a-zA-Z0-9-_]+)$", |params| {
+        params.get(0).map(|string_template| Route::SendTemplate {
+            template: string_template.to_string(),
+        })
+    });
+
+    router.add_route_with_params(r"^/batch/([a-zA-Z0-9-_]+)$", |params| {
+        params.get(0).map(|string_template| Route::SendBatch {
+            template: string_template.to_string(),
+        })
+    });
+
+    router.add_route(r"^/template-partials$", || Route::TemplatePartials);
+
+    router.add_route_with_params(r"^/template-partials/([a-zA-Z0-9-_]+)$", |params| {
+        params.get(0).map(|name| Route::TemplatePartial { name: name.to_string() })
+    });
+
+    router.add_route(r"^/dead-letters$", || Route::DeadLetters);
+
+    router.add_route_with_params(r"^/dead-letters/([a-zA-Z0-9-]+)$", |params| {
         params
             .get(0)
-            .and_then(|string_template| TemplateVariant::from_str(string_template).ok())
-            .map(|template| Route::Templates { template })
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::DeadLetter { id })
+    });
+
+    router.add_route_with_params(r"^/dead-letters/([a-zA-Z0-9-]+)/requeue$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::DeadLetterRequeue { id })
+    });
+
+    router.add_route_with_params(r"^/scheduled/([a-zA-Z0-9-]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::ScheduledEmail { id })
+    });
+
+    router.add_route(r"^/notifications$", || Route::Notifications);
+
+    router.add_route_with_params(r"^/notifications/([a-zA-Z0-9-]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::Notification { id })
+    });
+
+    router.add_route(r"^/notification-preferences$", || Route::NotificationPreferences);
+
+    router.add_route_with_params(r"^/notification-preferences/(\d+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::NotificationPreference { id })
+    });
+
+    router.add_route_with_params(r"^/unsubscribe/([a-zA-Z0-9-_]+\.[a-zA-Z0-9-_]+)$", |params| {
+        params.get(0).map(|token| Route::Unsubscribe { token: token.to_string() })
+    });
+
+    router.add_route(r"^/webhooks/sendgrid/events$", || Route::SendGridEvents);
+
+    router.add_route(r"^/reports/engagement$", || Route::EngagementReport);
+
+    router.add_route(r"^/suppressions$", || Route::Suppressions);
+
+    // the address is percent-encoded in the path, e.g. `a%2Bb%40storiqa.com`
+    router.add_route_with_params(r"^/suppressions/([^/]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|email| percent_decode(email.as_bytes()).decode_utf8().ok())
+            .map(|email| Route::Suppression { email: email.into_owned() })
     });
 
     router
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suppression_address_is_decoded() {
+        let router = create_route_parser();
+        assert_eq!(
+            router.test("/suppressions/a%2Bb%40storiqa.com"),
+            Some(Route::Suppression {
+                email: "a+b@storiqa.com".to_string()
+            })
+        );
+        assert_eq!(
+            router.test("/suppressions/user@storiqa.com"),
+            Some(Route::Suppression {
+                email: "user@storiqa.com".to_string()
+            })
+        );
+        assert_eq!(router.test("/suppressions/%FF"), None);
+    }
+}
@@ -2,6 +2,7 @@ use hyper::StatusCode;
 use serde_json;
 
 use stq_http::errors::{Codeable, PayloadCarrier};
+use validator::ValidationErrors;
 
 #[derive(Debug, Fail)]
 pub enum Error {
@@ -17,6 +18,12 @@ pub enum Error {
     Connection,
     #[fail(display = "Http client error")]
     HttpClient,
+    #[fail(display = "Smtp client error")]
+    SmtpClient,
+    #[fail(display = "Email provider rejected the message")]
+    ProviderRejected,
+    #[fail(display = "Recipient is on the suppression list")]
+    Suppressed(SuppressedRecipient),
 }
 
 #[derive(Debug, Serialize)]
@@ -25,14 +32,22 @@ pub struct EmarsysError {
     pub text: Option<String>,
 }
 
+/// Suppressed recipient the email wasn't sent to
+#[derive(Clone, Debug, Serialize)]
+pub struct SuppressedRecipient {
+    pub email: String,
+    pub reason: String,
+}
+
 impl Codeable for Error {
     fn code(&self) -> StatusCode {
         match *self {
             Error::NotFound => StatusCode::NotFound,
             Error::Emarsys(_) => StatusCode::BadRequest,
             Error::Parse => StatusCode::UnprocessableEntity,
-            Error::HttpClient | Error::Connection => StatusCode::InternalServerError,
+            Error::HttpClient | Error::SmtpClient | Error::Connection | Error::ProviderRejected => StatusCode::InternalServerError,
             Error::Forbidden => StatusCode::Forbidden,
+            Error::Suppressed(_) => StatusCode::Conflict,
         }
     }
 }
@@ -41,7 +56,27 @@ impl PayloadCarrier for Error {
     fn payload(&self) -> Option<serde_json::Value> {
         match *self {
             Error::Emarsys(ref e) => serde_json::to_value(e.clone()).ok(),
+            Error::Suppressed(ref recipient) => serde_json::to_value(recipient.clone()).ok(),
             _ => None,
         }
     }
 }
+
+/// Validation errors in a single line, e.g. `to: Invalid email address; send_at: range`
+pub fn validation_details(errors: &ValidationErrors) -> String {
+    let mut fields = errors.clone().inner().into_iter().collect::<Vec<_>>();
+    fields.sort_by_key(|&(field, _)| field);
+    fields
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.into_iter().map(move |error| {
+                let message = error
+                    .message
+                    .map(|message| message.to_string())
+                    .unwrap_or_else(|| error.code.to_string());
+                format!("{}: {}", field, message)
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -10,6 +10,8 @@ extern crate futures_cpupool;
 extern crate hyper;
 extern crate hyper_tls;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate log;
 extern crate r2d2;
 extern crate rand;
@@ -18,11 +20,18 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate handlebars;
+extern crate hmac;
+extern crate lettre;
+extern crate lettre_email;
 extern crate mime;
+extern crate native_tls;
+extern crate openssl;
+extern crate percent_encoding;
 extern crate serde_json;
 extern crate tokio_core;
 extern crate tokio_signal;
 extern crate uuid;
+extern crate validator;
 #[macro_use]
 extern crate sentry;
 extern crate base64;
@@ -47,6 +56,7 @@ pub mod services;
 
 use std::process;
 use std::sync::Arc;
+use std::time::Duration;
 
 use diesel::pg::PgConnection;
 use diesel::r2d2::ConnectionManager;
@@ -54,17 +64,25 @@ use futures::future;
 use futures::prelude::*;
 use futures_cpupool::CpuPool;
 use hyper::server::Http;
-use tokio_core::reactor::Core;
+use tokio_core::reactor::{Core, Interval};
 
 use stq_http::controller::Application;
 
-use controller::context::StaticContext;
+use controller::context::{DynamicContext, StaticContext};
 use repos::acl::RolesCacheImpl;
 use repos::repo_factory::ReposFactoryImpl;
+use sentry_integration::log_and_capture_error;
 use services::emarsys::{EmarsysClient, EmarsysClientImpl};
 use services::mocks::emarsys::EmarsysClientMock;
 use services::mocks::sendgrid::SendgridServiceMock;
+use services::mocks::users::UsersClientMock;
+use services::outbox::OutboxService;
+use services::scheduler::SchedulerService;
 use services::sendgrid::{SendgridService, SendgridServiceImpl};
+use services::smtp::SmtpServiceImpl;
+use services::transport_chain::TransportChain;
+use services::users::{UsersClient, UsersClientImpl};
+use services::Service;
 
 /// Starts new web service from provided `Config`
 pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: &Option<i32>, callback: F) {
@@ -105,16 +123,47 @@ pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: &Option
         })
     };
 
-    let sendgrid_service: Arc<SendgridService> = if config.testmode.as_ref().and_then(|t| t.get("sendgrid")) == Some(&config::ApiMode::Mock)
-    {
-        Arc::new(SendgridServiceMock)
+    let users_client: Arc<UsersClient> = if config.testmode.as_ref().and_then(|t| t.get("users")) == Some(&config::ApiMode::Mock) {
+        Arc::new(UsersClientMock)
     } else {
-        Arc::new(SendgridServiceImpl {
-            config: config.sendgrid.clone(),
+        Arc::new(UsersClientImpl {
+            config: config.users.clone(),
             client_handle: client_handle.clone(),
         })
     };
 
+    let mail_transports = config
+        .mail
+        .providers
+        .iter()
+        .map(|provider| {
+            let is_mock = config.testmode.as_ref().and_then(|t| t.get(provider.as_str())) == Some(&config::ApiMode::Mock);
+            let transport: Arc<SendgridService> = if is_mock {
+                Arc::new(SendgridServiceMock)
+            } else {
+                match *provider {
+                    config::MailProvider::Sendgrid => Arc::new(SendgridServiceImpl {
+                        config: config.sendgrid.clone(),
+                        client_handle: client_handle.clone(),
+                    }),
+                    config::MailProvider::Smtp => Arc::new(SmtpServiceImpl {
+                        config: config.smtp.clone().expect("Smtp config not found"),
+                        cpu_pool: cpu_pool.clone(),
+                    }),
+                }
+            };
+            (*provider, transport)
+        })
+        .collect();
+    let mail_transports = Arc::new(TransportChain::new(
+        mail_transports,
+        config.mail.failure_threshold,
+        Duration::from_millis(config.mail.cooldown_ms),
+    ));
+
+    let outbox_poll_interval = Duration::from_millis(config.outbox.poll_interval_ms);
+    let scheduler_poll_interval = Duration::from_millis(config.scheduler.poll_interval_ms);
+
     let context = StaticContext::new(
         db_pool,
         cpu_pool,
@@ -122,8 +171,11 @@ pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: &Option
         Arc::new(config),
         repo_factory,
         emarsys_client,
-        sendgrid_service,
+        mail_transports,
+        users_client,
     );
+    let outbox_context = context.clone();
+    let scheduler_context = context.clone();
 
     let serve = Http::new()
         .serve_addr_handle(&address, &*handle, move || {
@@ -150,6 +202,38 @@ pub fn start_server<F: FnOnce() + 'static>(config: config::Config, port: &Option
             .map_err(|_| ()),
     );
 
+    // Outbox worker
+    handle.spawn(
+        Interval::new(outbox_poll_interval, &*handle)
+            .expect("Could not create outbox worker timer")
+            .map_err(|e| error!("Outbox worker timer error: {}", e))
+            .for_each(move |_| {
+                let service = Service::new(outbox_context.clone(), DynamicContext::new(None, String::default(), None, vec![]));
+                service.deliver_pending_emails().then(|res| {
+                    if let Err(e) = res {
+                        log_and_capture_error(&e);
+                    }
+                    Ok(())
+                })
+            }),
+    );
+
+    // Scheduler of the emails sent with `send_at`
+    handle.spawn(
+        Interval::new(scheduler_poll_interval, &*handle)
+            .expect("Could not create scheduler timer")
+            .map_err(|e| error!("Scheduler timer error: {}", e))
+            .for_each(move |_| {
+                let service = Service::new(scheduler_context.clone(), DynamicContext::new(None, String::default(), None, vec![]));
+                service.release_scheduled_emails().then(|res| {
+                    if let Err(e) = res {
+                        log_and_capture_error(&e);
+                    }
+                    Ok(())
+                })
+            }),
+    );
+
     info!("Listening on http://{}, threads: {}", address, thread_count);
     handle.spawn_fn(move || {
         callback();
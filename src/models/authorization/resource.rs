@@ -3,6 +3,10 @@ use std::fmt;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Resource {
+    NotificationPreferences,
+    Notifications,
+    Outbox,
+    Suppressions,
     Templates,
     UserRoles,
 }
@@ -10,6 +14,10 @@ pub enum Resource {
 impl fmt::Display for Resource {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Resource::NotificationPreferences => write!(f, "notification preferences"),
+            Resource::Notifications => write!(f, "notifications"),
+            Resource::Outbox => write!(f, "outbox"),
+            Resource::Suppressions => write!(f, "suppressions"),
             Resource::Templates => write!(f, "templates"),
             Resource::UserRoles => write!(f, "user roles"),
         }
@@ -0,0 +1,70 @@
+//! Models of the mail payloads which aren't covered by the shared resources
+use chrono::{DateTime, Utc};
+use stq_static_resources::SimpleMail;
+
+/// File attached to the email, inline images are referred to from the HTML by `cid:<content_id>`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Attachment {
+    /// Base64 encoded content of the file
+    pub content: String,
+    pub filename: String,
+    /// MIME type of the file, e.g. `application/pdf`
+    #[serde(rename = "type")]
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_id: Option<String>,
+}
+
+/// Optional fields accepted by every send endpoint along with the email payload
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct MailOptions {
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    pub reply_to: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// Time to send the email at, it's sent right away if the time has passed
+    pub send_at: Option<DateTime<Utc>>,
+}
+
+/// Single address or a list of addresses
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum AddressList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl AddressList {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            AddressList::One(address) => vec![address],
+            AddressList::Many(addresses) => addresses,
+        }
+    }
+}
+
+/// Payload of `/simple-mail`
+#[derive(Deserialize, Clone, Debug)]
+pub struct SimpleMailPayload {
+    pub to: AddressList,
+    pub subject: String,
+    pub text: String,
+    #[serde(flatten)]
+    pub options: MailOptions,
+}
+
+impl SimpleMailPayload {
+    /// Mail to the first recipient and the rest of the recipients
+    pub fn into_send_mail(self) -> (SimpleMail, Vec<String>, MailOptions) {
+        let mut to = self.to.into_vec().into_iter();
+        let mail = SimpleMail {
+            to: to.next().unwrap_or_default(),
+            subject: self.subject,
+            text: self.text,
+        };
+        (mail, to.collect(), self.options)
+    }
+}
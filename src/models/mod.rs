@@ -1,11 +1,25 @@
 pub mod authorization;
 pub mod emarsys;
+pub mod mail;
+pub mod notification;
+pub mod notification_preference;
+pub mod outbox;
 pub mod sendgrid;
+pub mod sendgrid_event;
+pub mod suppression;
 pub mod template;
+pub mod user_account;
 pub mod user_role;
 
 pub use self::authorization::*;
 pub use self::emarsys::*;
+pub use self::mail::*;
+pub use self::notification::*;
+pub use self::notification_preference::*;
+pub use self::outbox::*;
 pub use self::sendgrid::*;
+pub use self::sendgrid_event::*;
+pub use self::suppression::*;
 pub use self::template::*;
+pub use self::user_account::*;
 pub use self::user_role::*;
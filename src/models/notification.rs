@@ -0,0 +1,206 @@
+//! Models for the delivery log of sent notifications
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use chrono::NaiveDate;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{BigInt, Date, VarChar};
+use failure::Error as FailureError;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use errors::Error;
+use schema::{notification_events, notifications};
+
+/// Delivery status of the notification
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[sql_type = "VarChar"]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationStatus {
+    /// Stored in the outbox, waiting for delivery
+    Queued,
+    /// Accepted by the provider
+    Sent,
+    /// Provider refused the message or retries are exhausted
+    Failed,
+    /// Recipient's mail server accepted the message
+    Delivered,
+    /// Recipient opened the message
+    Opened,
+    /// Recipient clicked a link in the message
+    Clicked,
+    /// Recipient's mail server rejected the message
+    Bounced,
+    /// Provider dropped the message, e.g. because the address is on its own suppression list
+    Dropped,
+    /// Recipient marked the message as spam
+    Complained,
+    /// Scheduled message cancelled before it was sent
+    Cancelled,
+}
+
+impl NotificationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            NotificationStatus::Queued => "queued",
+            NotificationStatus::Sent => "sent",
+            NotificationStatus::Failed => "failed",
+            NotificationStatus::Delivered => "delivered",
+            NotificationStatus::Opened => "opened",
+            NotificationStatus::Clicked => "clicked",
+            NotificationStatus::Bounced => "bounced",
+            NotificationStatus::Dropped => "dropped",
+            NotificationStatus::Complained => "complained",
+            NotificationStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Provider events arrive out of order, so the status only moves forward:
+    /// a late `delivered` event doesn't hide that the message was already opened.
+    pub fn is_superseded_by(&self, status: NotificationStatus) -> bool {
+        status.progress() > self.progress()
+    }
+
+    fn progress(&self) -> u8 {
+        match *self {
+            NotificationStatus::Queued => 0,
+            NotificationStatus::Sent => 1,
+            NotificationStatus::Delivered => 2,
+            NotificationStatus::Opened => 3,
+            NotificationStatus::Clicked => 4,
+            NotificationStatus::Failed | NotificationStatus::Bounced | NotificationStatus::Dropped | NotificationStatus::Cancelled => 5,
+            NotificationStatus::Complained => 6,
+        }
+    }
+}
+
+impl fmt::Display for NotificationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for NotificationStatus {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(NotificationStatus::Queued),
+            "sent" => Ok(NotificationStatus::Sent),
+            "failed" => Ok(NotificationStatus::Failed),
+            "delivered" => Ok(NotificationStatus::Delivered),
+            "opened" => Ok(NotificationStatus::Opened),
+            "clicked" => Ok(NotificationStatus::Clicked),
+            "bounced" => Ok(NotificationStatus::Bounced),
+            "dropped" => Ok(NotificationStatus::Dropped),
+            "complained" => Ok(NotificationStatus::Complained),
+            "cancelled" => Ok(NotificationStatus::Cancelled),
+            _ => Err(format_err!("Unknown notification status {}", s).context(Error::Parse).into()),
+        }
+    }
+}
+
+impl FromSql<VarChar, Pg> for NotificationStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<VarChar, Pg>>::from_sql(bytes)?;
+        NotificationStatus::from_str(&value).map_err(|_| format!("Unrecognized notification status {}", value).into())
+    }
+}
+
+impl ToSql<VarChar, Pg> for NotificationStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+/// Notification id is the id of the outbox message which delivers it
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct Notification {
+    pub id: Uuid,
+    pub recipient: String,
+    pub template: Option<String>,
+    pub subject: String,
+    pub status: NotificationStatus,
+    pub provider_message_id: Option<String>,
+    pub user_id: Option<UserId>,
+    pub sent_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    /// Provider which delivered the notification
+    pub provider: Option<String>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "notifications"]
+pub struct NewNotification {
+    pub id: Uuid,
+    pub recipient: String,
+    pub template: Option<String>,
+    pub subject: String,
+    pub user_id: Option<UserId>,
+}
+
+/// Provider event changing the status of the notification
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct NotificationEvent {
+    pub id: i32,
+    pub notification_id: Uuid,
+    pub status: NotificationStatus,
+    /// Id of the event assigned by the provider, events are recorded once
+    pub provider_event_id: Option<String>,
+    pub occurred_at: SystemTime,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "notification_events"]
+pub struct NewNotificationEvent {
+    pub notification_id: Uuid,
+    pub status: NotificationStatus,
+    pub provider_event_id: Option<String>,
+    pub occurred_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NotificationsSearch {
+    pub recipient: Option<String>,
+    pub template: Option<String>,
+    pub status: Option<NotificationStatus>,
+    pub provider: Option<String>,
+    pub created_from: Option<SystemTime>,
+    pub created_to: Option<SystemTime>,
+}
+
+/// Filter of the engagement report, only emails sent from templates are counted
+#[derive(Clone, Debug, Default)]
+pub struct EngagementSearch {
+    pub template: Option<String>,
+    pub sent_from: Option<SystemTime>,
+    pub sent_to: Option<SystemTime>,
+}
+
+/// Engagement with the emails of the template sent on the day
+#[derive(Clone, Debug, Serialize, QueryableByName)]
+pub struct EngagementStats {
+    #[sql_type = "VarChar"]
+    pub template: String,
+    #[sql_type = "Date"]
+    pub day: NaiveDate,
+    #[sql_type = "BigInt"]
+    pub sent: i64,
+    #[sql_type = "BigInt"]
+    pub delivered: i64,
+    #[sql_type = "BigInt"]
+    pub opened: i64,
+    #[sql_type = "BigInt"]
+    pub clicked: i64,
+    #[sql_type = "BigInt"]
+    pub bounced: i64,
+}
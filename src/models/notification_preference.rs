@@ -0,0 +1,176 @@
+//! Models for recipient preferences on categories of emails
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_static_resources::TemplateVariant;
+use stq_types::UserId;
+
+use errors::Error;
+use schema::notification_preferences;
+
+/// Category of emails, recipients can opt out of marketing and moderation ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[sql_type = "VarChar"]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationCategory {
+    /// Orders of the recipient and of their stores, never suppressed
+    Transactional,
+    /// Custom templates, e.g. promotions
+    Marketing,
+    /// Moderation of stores and products
+    Moderation,
+    /// Email verification and password reset, never suppressed
+    Security,
+}
+
+impl NotificationCategory {
+    /// Category of emails sent with the template, custom templates are marketing ones
+    pub fn of_template(template_name: &str) -> Self {
+        match TemplateVariant::from_str(template_name) {
+            Ok(TemplateVariant::OrderUpdateStateForUser)
+            | Ok(TemplateVariant::OrderUpdateStateForStore)
+            | Ok(TemplateVariant::OrderCreateForUser)
+            | Ok(TemplateVariant::OrderCreateForStore) => NotificationCategory::Transactional,
+            Ok(TemplateVariant::StoreModerationStatusForUser)
+            | Ok(TemplateVariant::BaseProductModerationStatusForUser)
+            | Ok(TemplateVariant::StoreModerationStatusForModerator)
+            | Ok(TemplateVariant::BaseProductModerationStatusForModerator) => NotificationCategory::Moderation,
+            Ok(TemplateVariant::EmailVerificationForUser)
+            | Ok(TemplateVariant::ApplyEmailVerificationForUser)
+            | Ok(TemplateVariant::PasswordResetForUser)
+            | Ok(TemplateVariant::ApplyPasswordResetForUser)
+            | Ok(TemplateVariant::WalletEmailVerificationForUser)
+            | Ok(TemplateVariant::WalletApplyEmailVerificationForUser)
+            | Ok(TemplateVariant::WalletPasswordResetForUser)
+            | Ok(TemplateVariant::WalletApplyPasswordResetForUser) => NotificationCategory::Security,
+            Ok(_) => NotificationCategory::Transactional,
+            Err(_) => NotificationCategory::Marketing,
+        }
+    }
+
+    /// Transactional and security emails are sent regardless of preferences and have no unsubscribe link
+    pub fn is_optional(&self) -> bool {
+        match *self {
+            NotificationCategory::Marketing | NotificationCategory::Moderation => true,
+            NotificationCategory::Transactional | NotificationCategory::Security => false,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            NotificationCategory::Transactional => "transactional",
+            NotificationCategory::Marketing => "marketing",
+            NotificationCategory::Moderation => "moderation",
+            NotificationCategory::Security => "security",
+        }
+    }
+}
+
+impl fmt::Display for NotificationCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for NotificationCategory {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transactional" => Ok(NotificationCategory::Transactional),
+            "marketing" => Ok(NotificationCategory::Marketing),
+            "moderation" => Ok(NotificationCategory::Moderation),
+            "security" => Ok(NotificationCategory::Security),
+            _ => Err(format_err!("Unknown notification category {}", s).context(Error::Parse).into()),
+        }
+    }
+}
+
+impl FromSql<VarChar, Pg> for NotificationCategory {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<VarChar, Pg>>::from_sql(bytes)?;
+        NotificationCategory::from_str(&value).map_err(|_| format!("Unrecognized notification category {}", value).into())
+    }
+}
+
+impl ToSql<VarChar, Pg> for NotificationCategory {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+/// Whether emails of the category are sent to the address, there is at most one per address and category
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct NotificationPreference {
+    pub id: i32,
+    /// Owner of the preference, `None` if it's recorded by an unsubscribe link
+    pub user_id: Option<UserId>,
+    pub email: String,
+    pub category: NotificationCategory,
+    pub enabled: bool,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "notification_preferences"]
+pub struct NewNotificationPreference {
+    pub user_id: Option<UserId>,
+    pub email: String,
+    pub category: NotificationCategory,
+    pub enabled: bool,
+}
+
+/// Payload of the preference update by its owner, it's applied to the address of their account
+#[derive(Clone, Debug, Deserialize)]
+pub struct UpdateNotificationPreference {
+    pub category: NotificationCategory,
+    pub enabled: bool,
+}
+
+/// Opt-out an unsubscribe link leads to, it's shown to the recipient before being recorded
+#[derive(Clone, Debug, Serialize)]
+pub struct UnsubscribeConfirmation {
+    pub email: String,
+    pub category: NotificationCategory,
+}
+
+/// Addresses are compared case-insensitively
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_categories() {
+        let category = |template: TemplateVariant| NotificationCategory::of_template(&template.to_string());
+        assert_eq!(category(TemplateVariant::OrderCreateForUser), NotificationCategory::Transactional);
+        assert_eq!(
+            category(TemplateVariant::StoreModerationStatusForUser),
+            NotificationCategory::Moderation
+        );
+        assert_eq!(category(TemplateVariant::PasswordResetForUser), NotificationCategory::Security);
+        assert_eq!(
+            category(TemplateVariant::WalletApplyEmailVerificationForUser),
+            NotificationCategory::Security
+        );
+        assert_eq!(NotificationCategory::of_template("black-friday"), NotificationCategory::Marketing);
+        assert!(NotificationCategory::Marketing.is_optional());
+        assert!(NotificationCategory::Moderation.is_optional());
+        assert!(!NotificationCategory::Transactional.is_optional());
+        assert!(!NotificationCategory::Security.is_optional());
+    }
+}
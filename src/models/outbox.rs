@@ -0,0 +1,212 @@
+//! Models for the outbox of outgoing emails
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use failure::Fail;
+use serde_json;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::SendGridPayload;
+use schema::{idempotency_keys, outbox_attempts, outbox_messages};
+
+/// Delivery status of the outbox message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[sql_type = "VarChar"]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    /// Waiting to be picked up by the outbox worker
+    Pending,
+    /// Claimed by the outbox worker
+    Sending,
+    /// Accepted by the provider
+    Sent,
+    /// Retries are exhausted or the provider rejected the message
+    DeadLetter,
+    /// Waiting for its send time, the scheduler moves it to the queue
+    Scheduled,
+    /// Scheduled message cancelled before its send time
+    Cancelled,
+}
+
+impl OutboxStatus {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            OutboxStatus::Pending => "pending",
+            OutboxStatus::Sending => "sending",
+            OutboxStatus::Sent => "sent",
+            OutboxStatus::DeadLetter => "dead_letter",
+            OutboxStatus::Scheduled => "scheduled",
+            OutboxStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl fmt::Display for OutboxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for OutboxStatus {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(OutboxStatus::Pending),
+            "sending" => Ok(OutboxStatus::Sending),
+            "sent" => Ok(OutboxStatus::Sent),
+            "dead_letter" => Ok(OutboxStatus::DeadLetter),
+            "scheduled" => Ok(OutboxStatus::Scheduled),
+            "cancelled" => Ok(OutboxStatus::Cancelled),
+            _ => Err(format_err!("Unknown outbox status {}", s).context(Error::Parse).into()),
+        }
+    }
+}
+
+impl FromSql<VarChar, Pg> for OutboxStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<VarChar, Pg>>::from_sql(bytes)?;
+        OutboxStatus::from_str(&value).map_err(|_| format!("Unrecognized outbox status {}", value).into())
+    }
+}
+
+impl ToSql<VarChar, Pg> for OutboxStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Queryable, QueryableByName)]
+#[table_name = "outbox_messages"]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    pub payload: serde_json::Value,
+    pub status: OutboxStatus,
+    pub attempts: i32,
+    pub next_attempt_at: SystemTime,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+impl OutboxMessage {
+    /// Restores provider payload stored in the outbox
+    pub fn sendgrid_payload(&self) -> Result<SendGridPayload, FailureError> {
+        serde_json::from_value(self.payload.clone()).map_err(|e| {
+            e.context(format!("Couldn't parse payload of outbox message {}", self.id))
+                .context(Error::Parse)
+                .into()
+        })
+    }
+}
+
+/// Email accepted by a send endpoint, a scheduled one can be cancelled by `id` until `send_at`
+#[derive(Clone, Debug, Serialize)]
+pub struct QueuedEmail {
+    pub id: Uuid,
+    pub status: OutboxStatus,
+    pub send_at: SystemTime,
+}
+
+impl<'a> From<&'a OutboxMessage> for QueuedEmail {
+    fn from(message: &'a OutboxMessage) -> Self {
+        Self {
+            id: message.id,
+            status: message.status,
+            send_at: message.next_attempt_at,
+        }
+    }
+}
+
+/// Outbox message with its delivery history
+#[derive(Clone, Debug, Serialize)]
+pub struct OutboxMessageDetails {
+    pub message: OutboxMessage,
+    pub attempts: Vec<OutboxAttempt>,
+}
+
+#[derive(Clone, Debug, Serialize, Insertable)]
+#[table_name = "outbox_messages"]
+pub struct NewOutboxMessage {
+    pub id: Uuid,
+    pub payload: serde_json::Value,
+    /// Pending by default
+    pub status: Option<OutboxStatus>,
+    /// Now by default
+    pub next_attempt_at: Option<SystemTime>,
+}
+
+impl NewOutboxMessage {
+    pub fn from_payload(payload: &SendGridPayload) -> Result<Self, FailureError> {
+        serde_json::to_value(payload)
+            .map(|payload| Self {
+                id: Uuid::new_v4(),
+                payload,
+                status: None,
+                next_attempt_at: None,
+            })
+            .map_err(|e| e.context("Couldn't serialize outbox payload").into())
+    }
+
+    /// Keeps the message out of the queue until `send_at`
+    pub fn scheduled_at(self, send_at: SystemTime) -> Self {
+        Self {
+            status: Some(OutboxStatus::Scheduled),
+            next_attempt_at: Some(send_at),
+            ..self
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct OutboxAttempt {
+    pub id: i32,
+    pub message_id: Uuid,
+    pub succeeded: bool,
+    pub error: Option<String>,
+    pub created_at: SystemTime,
+    /// Provider which delivered the message
+    pub provider: Option<String>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "outbox_attempts"]
+pub struct NewOutboxAttempt {
+    pub message_id: Uuid,
+    pub succeeded: bool,
+    pub error: Option<String>,
+    pub provider: Option<String>,
+}
+
+/// Idempotency key of the request which queued the message. Keys are
+/// unique within a `scope`, i.e. a template or the simple mail endpoint.
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "idempotency_keys"]
+pub struct NewIdempotencyKey {
+    pub key: String,
+    pub scope: String,
+    pub message_id: Uuid,
+}
+
+/// Rendered email which is about to be stored in the outbox
+#[derive(Clone, Debug)]
+pub struct OutgoingEmail {
+    pub payload: SendGridPayload,
+    pub template: Option<String>,
+    pub user_id: Option<UserId>,
+    pub idempotency_key: Option<String>,
+    /// Scope the idempotency key is unique within
+    pub scope: String,
+    /// Time to send the email at, `None` to send it right away
+    pub send_at: Option<SystemTime>,
+}
@@ -1,5 +1,10 @@
-use mime::Mime;
+use std::collections::{HashMap, HashSet};
+
+use mime::{Mime, TEXT_PLAIN};
 use stq_static_resources::*;
+use uuid::Uuid;
+
+use models::{normalize_email, Attachment, MailOptions, NOTIFICATION_ID_ARG};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SendGridPayload {
@@ -7,11 +12,25 @@ pub struct SendGridPayload {
     pub from: Address,
     pub subject: String,
     pub content: Vec<Content>,
+    /// Additional headers of the email, e.g. `List-Unsubscribe`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// Arguments SendGrid copies to the webhook events of the message
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub custom_args: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<SendGridAttachment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<Address>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Personalization {
     pub to: Vec<Address>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cc: Vec<Address>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bcc: Vec<Address>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +39,40 @@ pub struct Address {
     pub name: Option<String>,
 }
 
+impl From<String> for Address {
+    fn from(email: String) -> Self {
+        Self { email, name: None }
+    }
+}
+
+/// Attachment in the form of the SendGrid API, `content_id` is required for inline attachments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SendGridAttachment {
+    pub content: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub filename: String,
+    pub disposition: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_id: Option<String>,
+}
+
+impl From<Attachment> for SendGridAttachment {
+    fn from(attachment: Attachment) -> Self {
+        let disposition = match attachment.content_id {
+            Some(_) => "inline",
+            None => "attachment",
+        };
+        Self {
+            content: attachment.content,
+            type_field: attachment.mime_type,
+            filename: attachment.filename,
+            disposition: disposition.to_string(),
+            content_id: attachment.content_id,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Content {
     #[serde(rename = "type")]
@@ -36,7 +89,11 @@ impl SendGridPayload {
         });
 
         let mut personalizations: Vec<Personalization> = Vec::new();
-        personalizations.push(Personalization { to });
+        personalizations.push(Personalization {
+            to,
+            cc: vec![],
+            bcc: vec![],
+        });
 
         let from = Address {
             email: from_email,
@@ -56,16 +113,124 @@ impl SendGridPayload {
             from,
             subject,
             content,
+            headers: HashMap::new(),
+            custom_args: HashMap::new(),
+            attachments: vec![],
+            reply_to: None,
+        }
+    }
+
+    /// Adds plain text alternative of the content. SendGrid requires `text/plain` to go first.
+    pub fn with_plain_text(mut self, text: String) -> Self {
+        self.content.insert(
+            0,
+            Content {
+                type_field: TEXT_PLAIN.to_string(),
+                value: text,
+            },
+        );
+        self
+    }
+
+    /// Adds headers which make mail clients show their unsubscribe button, see RFC 2369 and RFC 8058
+    pub fn with_unsubscribe_url(mut self, url: String) -> Self {
+        self.headers.insert("List-Unsubscribe".to_string(), format!("<{}>", url));
+        self.headers
+            .insert("List-Unsubscribe-Post".to_string(), "List-Unsubscribe=One-Click".to_string());
+        self
+    }
+
+    /// Adds recipients along with the main one, copies, reply-to address and attachments, `send_at` is up to the outbox.
+    /// SendGrid refuses messages listing an address twice, so repeated addresses are skipped.
+    pub fn with_options(mut self, to: Vec<String>, options: MailOptions) -> Self {
+        let MailOptions {
+            cc,
+            bcc,
+            reply_to,
+            attachments,
+            ..
+        } = options;
+        let mut seen = self
+            .get_address_list()
+            .iter()
+            .map(|email| normalize_email(email))
+            .collect::<HashSet<_>>();
+        let mut unique = |addresses: Vec<String>| -> Vec<Address> {
+            addresses
+                .into_iter()
+                .filter(|email| seen.insert(normalize_email(email)))
+                .map(Address::from)
+                .collect()
+        };
+        let (to, cc, bcc) = (unique(to), unique(cc), unique(bcc));
+
+        if self.personalizations.is_empty() {
+            self.personalizations.push(Personalization {
+                to: vec![],
+                cc: vec![],
+                bcc: vec![],
+            });
         }
+        {
+            let personalization = &mut self.personalizations[0];
+            personalization.to.extend(to);
+            personalization.cc.extend(cc);
+            personalization.bcc.extend(bcc);
+        }
+
+        self.reply_to = reply_to.map(Address::from).or(self.reply_to);
+        self.attachments.extend(attachments.into_iter().map(SendGridAttachment::from));
+        self
+    }
+
+    /// Tags the message with the notification id, so that webhook events can be matched to the notification
+    pub fn with_notification_id(mut self, id: Uuid) -> Self {
+        self.custom_args.insert(NOTIFICATION_ID_ARG.to_string(), id.to_string());
+        self
     }
 
     pub fn get_address_list(&self) -> Vec<String> {
         let mut addresses = vec![];
         for p in &self.personalizations {
-            for a in &p.to {
+            for a in p.to.iter().chain(p.cc.iter()).chain(p.bcc.iter()) {
                 addresses.push(a.email.clone());
             }
         }
         addresses
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_options_skips_repeated_addresses() {
+        let mail = SimpleMail {
+            to: "owner@storiqa.com".to_string(),
+            subject: "Order".to_string(),
+            text: "Text".to_string(),
+        };
+        let options = MailOptions {
+            cc: vec!["Manager@Storiqa.com".to_string(), "owner@storiqa.com".to_string()],
+            bcc: vec!["support@storiqa.com".to_string(), "manager@storiqa.com".to_string()],
+            reply_to: Some("help@storiqa.com".to_string()),
+            ..MailOptions::default()
+        };
+        let payload = SendGridPayload::from_send_mail(mail, "noreply@storiqa.com".to_string(), "Storiqa".to_string(), TEXT_PLAIN)
+            .with_options(vec!["admin@storiqa.com".to_string(), "OWNER@storiqa.com".to_string()], options);
+
+        assert_eq!(payload.personalizations.len(), 1);
+        assert_eq!(
+            payload.get_address_list(),
+            vec![
+                "owner@storiqa.com",
+                "admin@storiqa.com",
+                "Manager@Storiqa.com",
+                "support@storiqa.com"
+            ]
+        );
+        assert_eq!(payload.personalizations[0].cc.len(), 1);
+        assert_eq!(payload.reply_to.map(|address| address.email), Some("help@storiqa.com".to_string()));
+    }
+}
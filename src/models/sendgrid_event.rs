@@ -0,0 +1,97 @@
+//! Models for the events SendGrid posts to the event webhook
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use models::{NotificationStatus, SuppressionReason};
+
+/// Custom argument carrying the id of the notification, SendGrid copies it to every event of the message
+pub const NOTIFICATION_ID_ARG: &'static str = "notification_id";
+
+/// Kind of the event, see https://sendgrid.com/docs/for-developers/tracking-events/event/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum SendGridEventKind {
+    #[serde(rename = "processed")]
+    Processed,
+    #[serde(rename = "deferred")]
+    Deferred,
+    #[serde(rename = "delivered")]
+    Delivered,
+    #[serde(rename = "open")]
+    Open,
+    #[serde(rename = "click")]
+    Click,
+    #[serde(rename = "bounce")]
+    Bounce,
+    #[serde(rename = "dropped")]
+    Dropped,
+    #[serde(rename = "spamreport")]
+    SpamReport,
+    #[serde(rename = "unsubscribe")]
+    Unsubscribe,
+    #[serde(rename = "group_unsubscribe")]
+    GroupUnsubscribe,
+    #[serde(rename = "group_resubscribe")]
+    GroupResubscribe,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SendGridEvent {
+    pub email: String,
+    /// Unix timestamp of the event
+    pub timestamp: u64,
+    pub event: SendGridEventKind,
+    pub sg_event_id: Option<String>,
+    pub sg_message_id: Option<String>,
+    /// `bounce` or `blocked` for bounce events
+    #[serde(rename = "type")]
+    pub bounce_type: Option<String>,
+    pub reason: Option<String>,
+    /// Custom argument set on delivery, see `NOTIFICATION_ID_ARG`
+    #[serde(rename = "notification_id")]
+    pub notification_id_arg: Option<String>,
+}
+
+impl SendGridEvent {
+    /// Id returned by SendGrid when the message was sent, `sg_message_id` is that id with the filter suffix appended
+    pub fn message_id(&self) -> Option<&str> {
+        self.sg_message_id
+            .as_ref()
+            .and_then(|id| id.split(".filter").next())
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Notification the event belongs to, taken from the custom argument set on delivery
+    pub fn notification_id(&self) -> Option<Uuid> {
+        self.notification_id_arg.as_ref().and_then(|id| id.parse().ok())
+    }
+
+    pub fn occurred_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+
+    /// Status of the notification after the event, `None` if the event doesn't change it
+    pub fn notification_status(&self) -> Option<NotificationStatus> {
+        match self.event {
+            SendGridEventKind::Delivered => Some(NotificationStatus::Delivered),
+            SendGridEventKind::Open => Some(NotificationStatus::Opened),
+            SendGridEventKind::Click => Some(NotificationStatus::Clicked),
+            SendGridEventKind::Bounce => Some(NotificationStatus::Bounced),
+            SendGridEventKind::Dropped => Some(NotificationStatus::Dropped),
+            SendGridEventKind::SpamReport => Some(NotificationStatus::Complained),
+            _ => None,
+        }
+    }
+
+    /// Reason to stop sending to the address. Blocked messages are rejected
+    /// temporarily, e.g. by a spam filter, so only hard bounces suppress the address.
+    pub fn suppression_reason(&self) -> Option<SuppressionReason> {
+        match self.event {
+            SendGridEventKind::Bounce if self.bounce_type.as_ref().map(String::as_str) != Some("blocked") => {
+                Some(SuppressionReason::HardBounce)
+            }
+            SendGridEventKind::SpamReport => Some(SuppressionReason::Complaint),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Models for the global list of addresses emails are never sent to
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use errors::Error;
+use schema::suppressions;
+
+/// Source of the suppressions added by admins
+pub const MANUAL_SUPPRESSION_SOURCE: &'static str = "admin";
+
+/// Why the address is suppressed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[sql_type = "VarChar"]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressionReason {
+    /// Mailbox doesn't exist or refuses all mail
+    HardBounce,
+    /// Recipient marked an email as spam
+    Complaint,
+    /// Added by an admin
+    Manual,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SuppressionReason::HardBounce => "hard_bounce",
+            SuppressionReason::Complaint => "complaint",
+            SuppressionReason::Manual => "manual",
+        }
+    }
+}
+
+impl fmt::Display for SuppressionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for SuppressionReason {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hard_bounce" => Ok(SuppressionReason::HardBounce),
+            "complaint" => Ok(SuppressionReason::Complaint),
+            "manual" => Ok(SuppressionReason::Manual),
+            _ => Err(format_err!("Unknown suppression reason {}", s).context(Error::Parse).into()),
+        }
+    }
+}
+
+impl FromSql<VarChar, Pg> for SuppressionReason {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<VarChar, Pg>>::from_sql(bytes)?;
+        SuppressionReason::from_str(&value).map_err(|_| format!("Unrecognized suppression reason {}", value).into())
+    }
+}
+
+impl ToSql<VarChar, Pg> for SuppressionReason {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct Suppression {
+    pub id: i32,
+    pub email: String,
+    pub reason: SuppressionReason,
+    /// Who suppressed the address, e.g. `admin` or the email provider
+    pub source: String,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "suppressions"]
+pub struct NewSuppression {
+    pub email: String,
+    pub reason: SuppressionReason,
+    pub source: String,
+}
+
+/// Payload of the admin endpoint, suppressions added by admins are manual if `reason` is not set
+#[derive(Clone, Debug, Deserialize)]
+pub struct CreateSuppression {
+    pub email: String,
+    pub reason: Option<SuppressionReason>,
+}
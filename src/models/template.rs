@@ -1,19 +1,207 @@
 //! Models for managing Templates
+use std::time::SystemTime;
 
-use schema::templates;
-use stq_static_resources::TemplateVariant;
+use chrono::{DateTime, Utc};
+use serde_json;
+
+use models::{AddressList, MailOptions};
+use schema::{template_partials, template_versions, templates};
+use stq_types::UserId;
+use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Queryable, Insertable, Debug)]
 #[table_name = "templates"]
 pub struct Template {
     pub id: i32,
-    pub name: TemplateVariant,
+    pub name: String,
     pub data: String,
+    pub locale: String,
+    /// Handlebars template of the subject, the subject given by the caller is used if it's not set
+    pub subject: Option<String>,
+    /// Handlebars template of the plain text part, it's generated from the HTML part if not set
+    pub plain_text: Option<String>,
+    pub updated_at: SystemTime,
+}
+
+/// Template in the list of templates, without its content
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TemplateSummary {
+    pub id: i32,
+    pub name: String,
+    pub locale: String,
+    pub updated_at: SystemTime,
+    /// Size of the HTML part in bytes
+    pub size: usize,
+}
+
+impl<'a> From<&'a Template> for TemplateSummary {
+    fn from(template: &'a Template) -> Self {
+        Self {
+            id: template.id,
+            name: template.name.clone(),
+            locale: template.locale.clone(),
+            updated_at: template.updated_at,
+            size: template.data.len(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Insertable, Clone, Debug)]
 #[table_name = "templates"]
 pub struct NewTemplate {
-    pub name: TemplateVariant,
+    pub name: String,
+    pub data: String,
+    pub locale: String,
+    pub subject: Option<String>,
+    pub plain_text: Option<String>,
+}
+
+/// Payload of the template creation, the default locale is used if `locale` is not set
+#[derive(Deserialize, Clone, Debug)]
+pub struct CreateTemplate {
+    pub name: String,
+    pub data: String,
+    pub locale: Option<String>,
+    pub subject: Option<String>,
+    pub plain_text: Option<String>,
+}
+
+/// Draft of the template to render instead of the stored one, with `context` as template data
+#[derive(Deserialize, Clone, Debug)]
+pub struct TemplatePreview {
+    pub context: serde_json::Value,
+    pub data: Option<String>,
+    pub subject: Option<String>,
+    pub plain_text: Option<String>,
+}
+
+/// Number of rows and the time the latest of them was changed. Any insert,
+/// update or delete changes it, so it tells if compiled templates are outdated.
+#[derive(Queryable, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ContentVersion {
+    pub count: i64,
+    pub updated_at: Option<SystemTime>,
+}
+
+/// Template rendered with the email data
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RenderedTemplate {
+    /// `None` if the subject given by the caller is sent
+    pub subject: Option<String>,
+    pub html: String,
+    pub plain_text: String,
+}
+
+/// Payload of the send endpoints with an optional locale of the email
+#[derive(Deserialize, Clone, Debug)]
+pub struct LocalizedMail<E> {
+    #[serde(flatten)]
+    pub mail: E,
+    /// Recipients along with the one of the email
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    pub locale: Option<String>,
+    #[serde(flatten)]
+    pub options: MailOptions,
+}
+
+/// Payload of the generic send endpoint, `context` is passed to the template as is
+#[derive(Deserialize, Clone, Debug)]
+pub struct TemplateMail {
+    pub to: AddressList,
+    pub subject: String,
+    #[serde(default)]
+    pub context: serde_json::Value,
+    pub locale: Option<String>,
+    #[serde(flatten)]
+    pub options: MailOptions,
+}
+
+/// Payload of the batch send endpoint. The context of the recipient is merged over the shared one.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BatchMail {
+    pub subject: String,
+    #[serde(default)]
+    pub context: serde_json::Value,
+    pub locale: Option<String>,
+    pub recipients: Vec<BatchRecipient>,
+    /// Time to send the emails at
+    pub send_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BatchRecipient {
+    pub to: String,
+    #[serde(default)]
+    pub context: serde_json::Value,
+    /// Overrides the locale of the batch
+    pub locale: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchRecipientStatus {
+    Queued,
+    /// Queued to be sent at `send_at` of the batch
+    Scheduled,
+    /// The recipient disabled the category of the template
+    Skipped,
+    Failed,
+}
+
+/// Outcome of the batch send for one recipient, `message_id` is the id of the queued outbox message
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BatchRecipientResult {
+    pub to: String,
+    pub status: BatchRecipientStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Brings locale to the form stored in templates, e.g. `pt_BR` to `pt-br`
+pub fn normalize_locale(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_lowercase()
+}
+
+/// Revision of the template content, every update of the template adds one
+#[derive(Serialize, Clone, Debug, Queryable)]
+pub struct TemplateVersion {
+    pub id: i32,
+    pub template_id: i32,
+    pub version: i32,
+    pub data: String,
+    pub author_id: Option<UserId>,
+    pub created_at: SystemTime,
+    pub subject: Option<String>,
+    pub plain_text: Option<String>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "template_versions"]
+pub struct NewTemplateVersion {
+    pub template_id: i32,
+    pub version: i32,
+    pub data: String,
+    pub author_id: Option<UserId>,
+    pub subject: Option<String>,
+    pub plain_text: Option<String>,
+}
+
+/// Shared piece of templates, e.g. the base layout, used as `{{> name}}` or `{{#> name}}...{{/name}}`
+#[derive(Serialize, Clone, Debug, Queryable)]
+pub struct TemplatePartial {
+    pub id: i32,
+    pub name: String,
+    pub data: String,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "template_partials"]
+pub struct NewTemplatePartial {
+    pub name: String,
     pub data: String,
 }
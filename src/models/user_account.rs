@@ -0,0 +1,9 @@
+//! Account of the user in the users microservice
+use stq_types::UserId;
+
+/// Fields of the account the service relies on, others are ignored
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserAccount {
+    pub id: UserId,
+    pub email: String,
+}
@@ -51,7 +51,18 @@ impl ApplicationAcl {
         let mut hash = ::std::collections::HashMap::new();
         hash.insert(
             UsersRole::Superuser,
-            vec![permission!(Resource::Templates), permission!(Resource::UserRoles)],
+            vec![
+                permission!(Resource::NotificationPreferences),
+                permission!(Resource::Notifications),
+                permission!(Resource::Outbox),
+                permission!(Resource::Suppressions),
+                permission!(Resource::Templates),
+                permission!(Resource::UserRoles),
+            ],
+        );
+        hash.insert(
+            UsersRole::User,
+            vec![permission!(Resource::NotificationPreferences, Action::All, Scope::Owned)],
         );
 
         ApplicationAcl {
@@ -1,11 +1,21 @@
 pub mod acl;
+pub mod notification_preferences;
+pub mod notifications;
+pub mod outbox;
 pub mod repo_factory;
+pub mod suppressions;
+pub mod template_partials;
 pub mod templates;
 pub mod types;
 pub mod user_roles;
 
 pub use self::acl::*;
+pub use self::notification_preferences::*;
+pub use self::notifications::*;
+pub use self::outbox::*;
 pub use self::repo_factory::*;
+pub use self::suppressions::*;
+pub use self::template_partials::*;
 pub use self::templates::*;
 pub use self::types::*;
 pub use self::user_roles::*;
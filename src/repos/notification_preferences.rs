@@ -0,0 +1,174 @@
+//! Repo for notification_preferences table. Preference enables or disables
+//! a category of emails for the address of its owner.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewNotificationPreference, NotificationCategory, NotificationPreference};
+use repos::legacy_acl::*;
+use schema::notification_preferences::dsl as NotificationPreferences;
+
+/// Notification preferences repository
+pub trait NotificationPreferencesRepo {
+    /// List preferences of the user
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<NotificationPreference>>;
+
+    /// Get preference of the address on the category
+    fn find(&self, email: &str, category: NotificationCategory) -> RepoResult<Option<NotificationPreference>>;
+
+    /// Create preference or change the existing one for the same address and category
+    fn upsert(&self, payload: NewNotificationPreference) -> RepoResult<NotificationPreference>;
+
+    /// Delete preference by id
+    fn delete(&self, id: i32) -> RepoResult<Option<NotificationPreference>>;
+}
+
+/// Implementation of NotificationPreferences trait
+pub struct NotificationPreferencesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, NotificationPreference>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> NotificationPreferencesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, NotificationPreference>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> NotificationPreferencesRepo
+    for NotificationPreferencesRepoImpl<'a, T>
+{
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<NotificationPreference>> {
+        debug!("List notification preferences of user {}.", user_id);
+        NotificationPreferences::notification_preferences
+            .filter(NotificationPreferences::user_id.eq(Some(user_id)))
+            .order((NotificationPreferences::email, NotificationPreferences::category))
+            .get_results::<NotificationPreference>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|preferences| {
+                for preference in &preferences {
+                    acl::check(&*self.acl, Resource::NotificationPreferences, Action::Read, self, Some(preference))?;
+                }
+                Ok(preferences)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List notification preferences of user {} error occurred", user_id))
+                    .into()
+            })
+    }
+
+    fn find(&self, email: &str, category: NotificationCategory) -> RepoResult<Option<NotificationPreference>> {
+        debug!("Find notification preference of {} on {}.", email, category);
+        NotificationPreferences::notification_preferences
+            .filter(NotificationPreferences::email.eq(email))
+            .filter(NotificationPreferences::category.eq(category))
+            .get_result::<NotificationPreference>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|preference| {
+                if let Some(ref preference) = preference {
+                    acl::check(&*self.acl, Resource::NotificationPreferences, Action::Read, self, Some(preference))?;
+                }
+                Ok(preference)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Find notification preference of {} on {} error occurred", email, category))
+                    .into()
+            })
+    }
+
+    fn upsert(&self, payload: NewNotificationPreference) -> RepoResult<NotificationPreference> {
+        debug!("Upsert notification preference {:?}.", payload);
+        NotificationPreferences::notification_preferences
+            .filter(NotificationPreferences::email.eq(&payload.email))
+            .filter(NotificationPreferences::category.eq(payload.category))
+            .get_result::<NotificationPreference>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|existing| match existing {
+                Some(existing) => {
+                    // only the owner of the address may change its preferences, preferences recorded
+                    // by an unsubscribe link are claimed by the account holding the address
+                    if existing.user_id.is_some() {
+                        acl::check(&*self.acl, Resource::NotificationPreferences, Action::Update, self, Some(&existing))?;
+                    }
+                    let user_id = payload.user_id.or(existing.user_id);
+                    let claimed = NotificationPreference { user_id, ..existing };
+                    acl::check(&*self.acl, Resource::NotificationPreferences, Action::Update, self, Some(&claimed))?;
+                    let filter = NotificationPreferences::notification_preferences.filter(NotificationPreferences::id.eq(claimed.id));
+                    diesel::update(filter)
+                        .set((
+                            NotificationPreferences::enabled.eq(payload.enabled),
+                            NotificationPreferences::user_id.eq(user_id),
+                        ))
+                        .get_result(self.db_conn)
+                        .map_err(From::from)
+                }
+                None => diesel::insert_into(NotificationPreferences::notification_preferences)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+                    .and_then(|preference: NotificationPreference| {
+                        acl::check(
+                            &*self.acl,
+                            Resource::NotificationPreferences,
+                            Action::Create,
+                            self,
+                            Some(&preference),
+                        )?;
+                        Ok(preference)
+                    }),
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Upsert notification preference {:?} error occurred", payload))
+                    .into()
+            })
+    }
+
+    fn delete(&self, id: i32) -> RepoResult<Option<NotificationPreference>> {
+        debug!("Delete notification preference {}.", id);
+        NotificationPreferences::notification_preferences
+            .find(id)
+            .get_result::<NotificationPreference>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|preference| match preference {
+                Some(preference) => {
+                    acl::check(
+                        &*self.acl,
+                        Resource::NotificationPreferences,
+                        Action::Delete,
+                        self,
+                        Some(&preference),
+                    )?;
+                    diesel::delete(NotificationPreferences::notification_preferences.find(id))
+                        .get_result(self.db_conn)
+                        .optional()
+                        .map_err(From::from)
+                }
+                None => Ok(None),
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete notification preference {} error occurred", id)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, NotificationPreference>
+    for NotificationPreferencesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id: UserId, scope: &Scope, obj: Option<&NotificationPreference>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.and_then(|preference| preference.user_id) == Some(user_id),
+        }
+    }
+}
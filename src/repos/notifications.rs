@@ -0,0 +1,272 @@
+//! Repo for notifications table. Notification is a delivery log entry
+//! of an email queued by the service.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::{Nullable, Timestamp, VarChar};
+use diesel::Connection;
+use failure::Error as FailureError;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{
+    EngagementSearch, EngagementStats, NewNotification, NewNotificationEvent, Notification, NotificationEvent, NotificationStatus,
+    NotificationsSearch,
+};
+use repos::legacy_acl::*;
+use schema::notification_events::dsl as NotificationEvents;
+use schema::notifications::dsl as Notifications;
+
+/// Notifications repository for the delivery log
+pub trait NotificationsRepo {
+    /// Create a new log entry
+    fn create(&self, payload: NewNotification) -> RepoResult<Notification>;
+
+    /// Get log entry by id
+    fn get(&self, id: Uuid) -> RepoResult<Option<Notification>>;
+
+    /// Search log entries, newest first
+    fn search(&self, search: NotificationsSearch, offset: i64, count: i64) -> RepoResult<Vec<Notification>>;
+
+    /// Set delivery status of the notification
+    fn update_status(&self, id: Uuid, status: NotificationStatus) -> RepoResult<Notification>;
+
+    /// Mark notification as delivered by `provider`
+    fn mark_sent(&self, id: Uuid, provider: &str) -> RepoResult<Notification>;
+
+    /// Get log entry by the id of the message assigned by the provider
+    fn find_by_provider_message_id(&self, provider_message_id: &str) -> RepoResult<Option<Notification>>;
+
+    /// Set id of the message assigned by the provider
+    fn set_provider_message_id(&self, id: Uuid, provider_message_id: &str) -> RepoResult<Notification>;
+
+    /// Record provider event of the notification, `None` if the event has already been recorded
+    fn add_event(&self, payload: NewNotificationEvent) -> RepoResult<Option<NotificationEvent>>;
+
+    /// Count emails sent from each template per day and how many of them were delivered, opened, clicked or bounced
+    fn engagement_report(&self, search: EngagementSearch) -> RepoResult<Vec<EngagementStats>>;
+}
+
+/// Implementation of Notifications trait
+pub struct NotificationsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Notification>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> NotificationsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Notification>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> NotificationsRepo
+    for NotificationsRepoImpl<'a, T>
+{
+    fn create(&self, payload: NewNotification) -> RepoResult<Notification> {
+        debug!("Create notification {:?}.", payload);
+        let query = diesel::insert_into(Notifications::notifications).values(&payload);
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|notification: Notification| {
+                acl::check(&*self.acl, Resource::Notifications, Action::Create, self, Some(&notification))?;
+                Ok(notification)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create notification {:?} error occurred", payload)).into())
+    }
+
+    fn get(&self, id: Uuid) -> RepoResult<Option<Notification>> {
+        debug!("Get notification {}.", id);
+        Notifications::notifications
+            .find(id)
+            .get_result::<Notification>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|notification| {
+                if let Some(ref notification) = notification {
+                    acl::check(&*self.acl, Resource::Notifications, Action::Read, self, Some(notification))?;
+                }
+                Ok(notification)
+            })
+            .map_err(|e: FailureError| e.context(format!("Get notification {} error occurred", id)).into())
+    }
+
+    fn search(&self, search: NotificationsSearch, offset: i64, count: i64) -> RepoResult<Vec<Notification>> {
+        debug!("Search notifications {:?}, offset {}, count {}.", search, offset, count);
+        let mut query = Notifications::notifications.into_boxed();
+        if let Some(ref recipient) = search.recipient {
+            query = query.filter(Notifications::recipient.eq(recipient));
+        }
+        if let Some(template) = search.template.clone() {
+            query = query.filter(Notifications::template.eq(template));
+        }
+        if let Some(status) = search.status {
+            query = query.filter(Notifications::status.eq(status));
+        }
+        if let Some(ref provider) = search.provider {
+            query = query.filter(Notifications::provider.eq(provider));
+        }
+        if let Some(created_from) = search.created_from {
+            query = query.filter(Notifications::created_at.ge(created_from));
+        }
+        if let Some(created_to) = search.created_to {
+            query = query.filter(Notifications::created_at.lt(created_to));
+        }
+
+        query
+            .order(Notifications::created_at.desc())
+            .offset(offset)
+            .limit(count)
+            .get_results::<Notification>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|notifications| {
+                for notification in &notifications {
+                    acl::check(&*self.acl, Resource::Notifications, Action::Read, self, Some(notification))?;
+                }
+                Ok(notifications)
+            })
+            .map_err(|e: FailureError| e.context(format!("Search notifications {:?} error occurred", search)).into())
+    }
+
+    fn update_status(&self, id: Uuid, status: NotificationStatus) -> RepoResult<Notification> {
+        debug!("Update notification {} status to {}.", id, status);
+        let filter = Notifications::notifications.filter(Notifications::id.eq(id));
+        let query = diesel::update(filter).set(Notifications::status.eq(status));
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|notification: Notification| {
+                acl::check(&*self.acl, Resource::Notifications, Action::Update, self, Some(&notification))?;
+                Ok(notification)
+            })
+            .map_err(|e: FailureError| e.context(format!("Update notification {} status error occurred", id)).into())
+    }
+
+    fn mark_sent(&self, id: Uuid, provider: &str) -> RepoResult<Notification> {
+        debug!("Mark notification {} as sent by {}.", id, provider);
+        let filter = Notifications::notifications.filter(Notifications::id.eq(id));
+        let query = diesel::update(filter).set((
+            Notifications::status.eq(NotificationStatus::Sent),
+            Notifications::provider.eq(provider),
+            Notifications::sent_at.eq(SystemTime::now()),
+        ));
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|notification: Notification| {
+                acl::check(&*self.acl, Resource::Notifications, Action::Update, self, Some(&notification))?;
+                Ok(notification)
+            })
+            .map_err(|e: FailureError| e.context(format!("Mark notification {} as sent error occurred", id)).into())
+    }
+
+    fn find_by_provider_message_id(&self, provider_message_id: &str) -> RepoResult<Option<Notification>> {
+        debug!("Find notification by provider message id {}.", provider_message_id);
+        Notifications::notifications
+            .filter(Notifications::provider_message_id.eq(provider_message_id))
+            .get_result::<Notification>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|notification| {
+                if let Some(ref notification) = notification {
+                    acl::check(&*self.acl, Resource::Notifications, Action::Read, self, Some(notification))?;
+                }
+                Ok(notification)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Find notification by provider message id {} error occurred",
+                    provider_message_id
+                ))
+                .into()
+            })
+    }
+
+    fn set_provider_message_id(&self, id: Uuid, provider_message_id: &str) -> RepoResult<Notification> {
+        debug!("Set notification {} provider message id to {}.", id, provider_message_id);
+        let filter = Notifications::notifications.filter(Notifications::id.eq(id));
+        let query = diesel::update(filter).set(Notifications::provider_message_id.eq(provider_message_id));
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|notification: Notification| {
+                acl::check(&*self.acl, Resource::Notifications, Action::Update, self, Some(&notification))?;
+                Ok(notification)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Set notification {} provider message id error occurred", id))
+                    .into()
+            })
+    }
+
+    fn add_event(&self, payload: NewNotificationEvent) -> RepoResult<Option<NotificationEvent>> {
+        debug!("Add notification event {:?}.", payload);
+        acl::check(&*self.acl, Resource::Notifications, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::insert_into(NotificationEvents::notification_events)
+                    .values(&payload)
+                    .on_conflict(NotificationEvents::provider_event_id)
+                    .do_nothing()
+                    .get_result(self.db_conn)
+                    .optional()
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Add notification event {:?} error occurred", payload)).into())
+    }
+
+    fn engagement_report(&self, search: EngagementSearch) -> RepoResult<Vec<EngagementStats>> {
+        debug!("Engagement report {:?}.", search);
+        // emails are grouped by the day they were sent, so the numbers of one day
+        // don't change the day after when late opens and clicks arrive. A click means
+        // the email was opened even if the open wasn't tracked, e.g. images were blocked
+        let query = diesel::sql_query(
+            "SELECT n.template, n.sent_at::date AS day, COUNT(*) AS sent, \
+                COUNT(*) FILTER (WHERE e.delivered) AS delivered, \
+                COUNT(*) FILTER (WHERE e.opened OR e.clicked) AS opened, \
+                COUNT(*) FILTER (WHERE e.clicked) AS clicked, \
+                COUNT(*) FILTER (WHERE e.bounced) AS bounced \
+             FROM notifications n \
+             LEFT JOIN ( \
+                SELECT notification_id, \
+                    bool_or(status = 'delivered') AS delivered, \
+                    bool_or(status = 'opened') AS opened, \
+                    bool_or(status = 'clicked') AS clicked, \
+                    bool_or(status = 'bounced') AS bounced \
+                FROM notification_events GROUP BY notification_id \
+             ) e ON e.notification_id = n.id \
+             WHERE n.template IS NOT NULL AND n.sent_at IS NOT NULL \
+                AND ($1::varchar IS NULL OR n.template = $1) \
+                AND ($2::timestamp IS NULL OR n.sent_at >= $2) \
+                AND ($3::timestamp IS NULL OR n.sent_at < $3) \
+             GROUP BY n.template, day \
+             ORDER BY day, n.template",
+        )
+        .bind::<Nullable<VarChar>, _>(search.template.clone())
+        .bind::<Nullable<Timestamp>, _>(search.sent_from)
+        .bind::<Nullable<Timestamp>, _>(search.sent_to);
+
+        acl::check(&*self.acl, Resource::Notifications, Action::Read, self, None)
+            .and_then(|_| query.get_results::<EngagementStats>(self.db_conn).map_err(From::from))
+            .map_err(|e: FailureError| e.context(format!("Engagement report {:?} error occurred", search)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Notification>
+    for NotificationsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id: UserId, scope: &Scope, obj: Option<&Notification>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.and_then(|notification| notification.user_id) == Some(user_id),
+        }
+    }
+}
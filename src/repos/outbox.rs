@@ -0,0 +1,297 @@
+//! Repo for outbox_messages and outbox_attempts tables. Every outgoing email
+//! is stored here first and then delivered by the outbox worker.
+
+use std::time::{Duration, SystemTime};
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::{BigInt, Timestamp};
+use diesel::Connection;
+use failure::Error as FailureError;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewIdempotencyKey, NewOutboxAttempt, NewOutboxMessage, OutboxAttempt, OutboxMessage, OutboxStatus};
+use repos::legacy_acl::*;
+use schema::idempotency_keys::dsl as IdempotencyKeys;
+use schema::outbox_attempts::dsl as OutboxAttempts;
+use schema::outbox_messages;
+use schema::outbox_messages::dsl as OutboxMessages;
+
+/// Outbox repository for handling outgoing emails
+pub trait OutboxRepo {
+    /// Store a new message for delivery
+    fn create(&self, payload: NewOutboxMessage) -> RepoResult<OutboxMessage>;
+
+    /// Claim up to `limit` messages which are due for delivery. Claimed messages
+    /// are not handed out again until `lease` expires.
+    fn claim_due(&self, limit: i64, lease: Duration) -> RepoResult<Vec<OutboxMessage>>;
+
+    /// Store delivery attempt and move message to `status`
+    fn record_attempt(&self, attempt: NewOutboxAttempt, status: OutboxStatus, next_attempt_at: SystemTime) -> RepoResult<OutboxMessage>;
+
+    /// List delivery attempts of the message
+    fn list_attempts(&self, message_id: Uuid) -> RepoResult<Vec<OutboxAttempt>>;
+
+    /// Get message by id
+    fn get(&self, message_id: Uuid) -> RepoResult<Option<OutboxMessage>>;
+
+    /// List messages with the status, oldest first
+    fn list_by_status(&self, status: OutboxStatus, offset: i64, count: i64) -> RepoResult<Vec<OutboxMessage>>;
+
+    /// Move dead letter back to the queue with a fresh attempts counter
+    fn requeue(&self, message_id: Uuid) -> RepoResult<Option<OutboxMessage>>;
+
+    /// Move scheduled messages which are due to the queue
+    fn release_scheduled(&self) -> RepoResult<Vec<OutboxMessage>>;
+
+    /// Cancel the message if it's still scheduled
+    fn cancel(&self, message_id: Uuid) -> RepoResult<Option<OutboxMessage>>;
+
+    /// Find message queued with the idempotency key after `since`
+    fn find_by_idempotency_key(&self, key: &str, scope: &str, since: SystemTime) -> RepoResult<Option<OutboxMessage>>;
+
+    /// Bind idempotency key to the message, replacing an expired binding of the key
+    fn save_idempotency_key(&self, payload: NewIdempotencyKey) -> RepoResult<()>;
+}
+
+/// Implementation of Outbox trait
+pub struct OutboxRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, OutboxMessage>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> OutboxRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, OutboxMessage>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> OutboxRepo for OutboxRepoImpl<'a, T> {
+    fn create(&self, payload: NewOutboxMessage) -> RepoResult<OutboxMessage> {
+        debug!("Create outbox message {}.", payload.id);
+        let query = diesel::insert_into(OutboxMessages::outbox_messages).values(&payload);
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|message: OutboxMessage| {
+                acl::check(&*self.acl, Resource::Outbox, Action::Create, self, Some(&message))?;
+                Ok(message)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create outbox message {} error occurred", payload.id)).into())
+    }
+
+    fn claim_due(&self, limit: i64, lease: Duration) -> RepoResult<Vec<OutboxMessage>> {
+        let now = SystemTime::now();
+        let query = diesel::sql_query(
+            "UPDATE outbox_messages SET status = 'sending', next_attempt_at = $1 \
+             WHERE id IN ( \
+                SELECT id FROM outbox_messages \
+                WHERE status IN ('pending', 'sending') AND next_attempt_at <= $2 \
+                ORDER BY next_attempt_at \
+                LIMIT $3 \
+                FOR UPDATE SKIP LOCKED \
+             ) RETURNING *",
+        )
+        .bind::<Timestamp, _>(now + lease)
+        .bind::<Timestamp, _>(now)
+        .bind::<BigInt, _>(limit);
+
+        query
+            .get_results::<OutboxMessage>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|messages| {
+                for message in &messages {
+                    acl::check(&*self.acl, Resource::Outbox, Action::Update, self, Some(message))?;
+                }
+                Ok(messages)
+            })
+            .map_err(|e: FailureError| e.context("Claim due outbox messages error occurred").into())
+    }
+
+    fn record_attempt(&self, attempt: NewOutboxAttempt, status: OutboxStatus, next_attempt_at: SystemTime) -> RepoResult<OutboxMessage> {
+        debug!("Record outbox attempt {:?}, new status {}.", attempt, status);
+        self.db_conn
+            .transaction::<OutboxMessage, FailureError, _>(|| {
+                diesel::insert_into(OutboxAttempts::outbox_attempts)
+                    .values(&attempt)
+                    .execute(self.db_conn)?;
+
+                let filter = OutboxMessages::outbox_messages.filter(OutboxMessages::id.eq(attempt.message_id));
+                let message: OutboxMessage = diesel::update(filter)
+                    .set((
+                        OutboxMessages::status.eq(status),
+                        OutboxMessages::attempts.eq(OutboxMessages::attempts + 1),
+                        OutboxMessages::next_attempt_at.eq(next_attempt_at),
+                    ))
+                    .get_result(self.db_conn)?;
+                acl::check(&*self.acl, Resource::Outbox, Action::Update, self, Some(&message))?;
+                Ok(message)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Record attempt for outbox message {} error occurred", attempt.message_id))
+                    .into()
+            })
+    }
+
+    fn list_attempts(&self, message_id: Uuid) -> RepoResult<Vec<OutboxAttempt>> {
+        debug!("List attempts of outbox message {}.", message_id);
+        OutboxMessages::outbox_messages
+            .find(message_id)
+            .get_result::<OutboxMessage>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|message| acl::check(&*self.acl, Resource::Outbox, Action::Read, self, Some(&message)))
+            .and_then(|_| {
+                OutboxAttempts::outbox_attempts
+                    .filter(OutboxAttempts::message_id.eq(message_id))
+                    .order(OutboxAttempts::id)
+                    .get_results(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("List attempts of outbox message {} error occurred", message_id)).into())
+    }
+
+    fn get(&self, message_id: Uuid) -> RepoResult<Option<OutboxMessage>> {
+        debug!("Get outbox message {}.", message_id);
+        OutboxMessages::outbox_messages
+            .find(message_id)
+            .get_result::<OutboxMessage>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|message| {
+                if let Some(ref message) = message {
+                    acl::check(&*self.acl, Resource::Outbox, Action::Read, self, Some(message))?;
+                }
+                Ok(message)
+            })
+            .map_err(|e: FailureError| e.context(format!("Get outbox message {} error occurred", message_id)).into())
+    }
+
+    fn list_by_status(&self, status_arg: OutboxStatus, offset: i64, count: i64) -> RepoResult<Vec<OutboxMessage>> {
+        debug!("List outbox messages with status {}, offset {}, count {}.", status_arg, offset, count);
+        OutboxMessages::outbox_messages
+            .filter(OutboxMessages::status.eq(status_arg))
+            .order(OutboxMessages::created_at)
+            .offset(offset)
+            .limit(count)
+            .get_results::<OutboxMessage>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|messages| {
+                for message in &messages {
+                    acl::check(&*self.acl, Resource::Outbox, Action::Read, self, Some(message))?;
+                }
+                Ok(messages)
+            })
+            .map_err(|e: FailureError| e.context(format!("List outbox messages with status {} error occurred", status_arg)).into())
+    }
+
+    fn requeue(&self, message_id: Uuid) -> RepoResult<Option<OutboxMessage>> {
+        debug!("Requeue outbox message {}.", message_id);
+        acl::check(&*self.acl, Resource::Outbox, Action::Update, self, None)
+            .and_then(|_| {
+                let filter = OutboxMessages::outbox_messages
+                    .filter(OutboxMessages::id.eq(message_id))
+                    .filter(OutboxMessages::status.eq(OutboxStatus::DeadLetter));
+                diesel::update(filter)
+                    .set((
+                        OutboxMessages::status.eq(OutboxStatus::Pending),
+                        OutboxMessages::attempts.eq(0),
+                        OutboxMessages::next_attempt_at.eq(SystemTime::now()),
+                    ))
+                    .get_result::<OutboxMessage>(self.db_conn)
+                    .optional()
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Requeue outbox message {} error occurred", message_id)).into())
+    }
+
+    fn release_scheduled(&self) -> RepoResult<Vec<OutboxMessage>> {
+        let filter = OutboxMessages::outbox_messages
+            .filter(OutboxMessages::status.eq(OutboxStatus::Scheduled))
+            .filter(OutboxMessages::next_attempt_at.le(SystemTime::now()));
+        diesel::update(filter)
+            .set(OutboxMessages::status.eq(OutboxStatus::Pending))
+            .get_results::<OutboxMessage>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|messages| {
+                for message in &messages {
+                    acl::check(&*self.acl, Resource::Outbox, Action::Update, self, Some(message))?;
+                }
+                Ok(messages)
+            })
+            .map_err(|e: FailureError| e.context("Release scheduled outbox messages error occurred").into())
+    }
+
+    fn cancel(&self, message_id: Uuid) -> RepoResult<Option<OutboxMessage>> {
+        debug!("Cancel outbox message {}.", message_id);
+        acl::check(&*self.acl, Resource::Outbox, Action::Update, self, None)
+            .and_then(|_| {
+                let filter = OutboxMessages::outbox_messages
+                    .filter(OutboxMessages::id.eq(message_id))
+                    .filter(OutboxMessages::status.eq(OutboxStatus::Scheduled));
+                diesel::update(filter)
+                    .set(OutboxMessages::status.eq(OutboxStatus::Cancelled))
+                    .get_result::<OutboxMessage>(self.db_conn)
+                    .optional()
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Cancel outbox message {} error occurred", message_id)).into())
+    }
+
+    fn find_by_idempotency_key(&self, key: &str, scope: &str, since: SystemTime) -> RepoResult<Option<OutboxMessage>> {
+        debug!("Find outbox message by idempotency key {} in scope {}.", key, scope);
+        IdempotencyKeys::idempotency_keys
+            .inner_join(OutboxMessages::outbox_messages)
+            .filter(IdempotencyKeys::key.eq(key))
+            .filter(IdempotencyKeys::scope.eq(scope))
+            .filter(IdempotencyKeys::created_at.gt(since))
+            .select(outbox_messages::all_columns)
+            .get_result::<OutboxMessage>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|message| {
+                if let Some(ref message) = message {
+                    acl::check(&*self.acl, Resource::Outbox, Action::Read, self, Some(message))?;
+                }
+                Ok(message)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Find outbox message by idempotency key {} error occurred", key))
+                    .into()
+            })
+    }
+
+    fn save_idempotency_key(&self, payload: NewIdempotencyKey) -> RepoResult<()> {
+        debug!("Save idempotency key {:?}.", payload);
+        acl::check(&*self.acl, Resource::Outbox, Action::Create, self, None)
+            .and_then(|_| {
+                let expired = IdempotencyKeys::idempotency_keys
+                    .filter(IdempotencyKeys::key.eq(&payload.key))
+                    .filter(IdempotencyKeys::scope.eq(&payload.scope));
+                diesel::delete(expired).execute(self.db_conn)?;
+                diesel::insert_into(IdempotencyKeys::idempotency_keys)
+                    .values(&payload)
+                    .execute(self.db_conn)?;
+                Ok(())
+            })
+            .map_err(|e: FailureError| e.context(format!("Save idempotency key {} error occurred", payload.key)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, OutboxMessage>
+    for OutboxRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&OutboxMessage>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -10,7 +10,18 @@ use repos::legacy_acl::{Acl, SystemACL, UnauthorizedACL};
 use repos::*;
 
 pub trait ReposFactory<C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static>: Clone + Send + 'static {
+    fn create_notification_preferences_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<NotificationPreferencesRepo + 'a>;
+    fn create_notification_preferences_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<NotificationPreferencesRepo + 'a>;
+    fn create_notifications_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<NotificationsRepo + 'a>;
+    fn create_notifications_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<NotificationsRepo + 'a>;
+    fn create_outbox_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<OutboxRepo + 'a>;
+    fn create_outbox_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<OutboxRepo + 'a>;
+    fn create_suppressions_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<SuppressionsRepo + 'a>;
+    fn create_suppressions_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<SuppressionsRepo + 'a>;
     fn create_templates_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<TemplatesRepo + 'a>;
+    fn create_templates_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<TemplatesRepo + 'a>;
+    fn create_template_partials_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<TemplatePartialsRepo + 'a>;
+    fn create_template_partials_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<TemplatePartialsRepo + 'a>;
     fn create_user_roles_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserRolesRepo + 'a>;
     fn create_user_roles_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserRolesRepo + 'a>;
 }
@@ -52,6 +63,42 @@ impl ReposFactoryImpl {
 }
 
 impl<C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ReposFactory<C> for ReposFactoryImpl {
+    fn create_notification_preferences_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<NotificationPreferencesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(NotificationPreferencesRepoImpl::new(db_conn, acl)) as Box<NotificationPreferencesRepo>
+    }
+
+    fn create_notification_preferences_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<NotificationPreferencesRepo + 'a> {
+        Box::new(NotificationPreferencesRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, NotificationPreference>>,
+        )) as Box<NotificationPreferencesRepo>
+    }
+
+    fn create_notifications_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<NotificationsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(NotificationsRepoImpl::new(db_conn, acl)) as Box<NotificationsRepo>
+    }
+
+    fn create_notifications_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<NotificationsRepo + 'a> {
+        Box::new(NotificationsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, Notification>>,
+        )) as Box<NotificationsRepo>
+    }
+
+    fn create_outbox_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<OutboxRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(OutboxRepoImpl::new(db_conn, acl)) as Box<OutboxRepo>
+    }
+
+    fn create_outbox_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<OutboxRepo + 'a> {
+        Box::new(OutboxRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, OutboxMessage>>,
+        )) as Box<OutboxRepo>
+    }
+
     fn create_user_roles_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserRolesRepo + 'a> {
         Box::new(UserRolesRepoImpl::new(
             db_conn,
@@ -65,10 +112,41 @@ impl<C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> +
         Box::new(UserRolesRepoImpl::new(db_conn, acl, self.roles_cache.clone())) as Box<UserRolesRepo>
     }
 
+    fn create_suppressions_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<SuppressionsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(SuppressionsRepoImpl::new(db_conn, acl)) as Box<SuppressionsRepo>
+    }
+
+    fn create_suppressions_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<SuppressionsRepo + 'a> {
+        Box::new(SuppressionsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, Suppression>>,
+        )) as Box<SuppressionsRepo>
+    }
+
     fn create_templates_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<TemplatesRepo + 'a> {
         let acl = self.get_acl(db_conn, user_id);
         Box::new(TemplatesRepoImpl::new(db_conn, acl)) as Box<TemplatesRepo>
     }
+
+    fn create_templates_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<TemplatesRepo + 'a> {
+        Box::new(TemplatesRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, Template>>,
+        )) as Box<TemplatesRepo>
+    }
+
+    fn create_template_partials_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<TemplatePartialsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(TemplatePartialsRepoImpl::new(db_conn, acl)) as Box<TemplatePartialsRepo>
+    }
+
+    fn create_template_partials_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<TemplatePartialsRepo + 'a> {
+        Box::new(TemplatePartialsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, TemplatePartial>>,
+        )) as Box<TemplatePartialsRepo>
+    }
 }
 
 #[cfg(test)]
@@ -79,7 +157,7 @@ pub mod tests {
     use std::error::Error;
     use std::fmt;
     use std::sync::Arc;
-    use std::time::SystemTime;
+    use std::time::{Duration, SystemTime};
 
     use diesel::connection::AnsiTransactionManager;
     use diesel::connection::SimpleConnection;
@@ -96,17 +174,22 @@ pub mod tests {
     use futures::Stream;
     use futures_cpupool::CpuPool;
     use r2d2::ManageConnection;
+    use serde_json;
     use tokio_core::reactor::Handle;
+    use uuid::Uuid;
 
     use stq_static_resources::*;
     use stq_types::*;
 
-    use config::Config;
+    use config::{Config, MailProvider};
     use controller::context::{DynamicContext, StaticContext};
     use models::*;
     use repos::*;
     use services::mocks::emarsys::EmarsysClientMock;
     use services::mocks::sendgrid::SendgridServiceMock;
+    use services::mocks::users::UsersClientMock;
+    use services::sendgrid::SendgridService;
+    use services::transport_chain::TransportChain;
     use services::*;
 
     pub const MOCK_REPO_FACTORY: ReposFactoryMock = ReposFactoryMock {};
@@ -133,9 +216,14 @@ pub mod tests {
             Arc::new(config),
             MOCK_REPO_FACTORY,
             Arc::new(emarsys_client_mock),
-            Arc::new(SendgridServiceMock),
+            Arc::new(TransportChain::new(
+                vec![(MailProvider::Sendgrid, Arc::new(SendgridServiceMock) as Arc<SendgridService>)],
+                5,
+                Duration::from_secs(60),
+            )),
+            Arc::new(UsersClientMock),
         );
-        let dynamic_context = DynamicContext::new(user_id, String::default());
+        let dynamic_context = DynamicContext::new(user_id, String::default(), None, vec![]);
 
         Service::new(static_context, dynamic_context)
     }
@@ -144,6 +232,34 @@ pub mod tests {
     pub struct ReposFactoryMock;
 
     impl<C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ReposFactory<C> for ReposFactoryMock {
+        fn create_notification_preferences_repo<'a>(
+            &self,
+            _db_conn: &'a C,
+            _user_id: Option<UserId>,
+        ) -> Box<NotificationPreferencesRepo + 'a> {
+            Box::new(NotificationPreferencesRepoMock::default()) as Box<NotificationPreferencesRepo>
+        }
+
+        fn create_notification_preferences_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<NotificationPreferencesRepo + 'a> {
+            Box::new(NotificationPreferencesRepoMock::default()) as Box<NotificationPreferencesRepo>
+        }
+
+        fn create_notifications_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<NotificationsRepo + 'a> {
+            Box::new(NotificationsRepoMock::default()) as Box<NotificationsRepo>
+        }
+
+        fn create_notifications_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<NotificationsRepo + 'a> {
+            Box::new(NotificationsRepoMock::default()) as Box<NotificationsRepo>
+        }
+
+        fn create_outbox_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<OutboxRepo + 'a> {
+            Box::new(OutboxRepoMock::default()) as Box<OutboxRepo>
+        }
+
+        fn create_outbox_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<OutboxRepo + 'a> {
+            Box::new(OutboxRepoMock::default()) as Box<OutboxRepo>
+        }
+
         fn create_user_roles_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<UserRolesRepo + 'a> {
             Box::new(UserRolesRepoMock::default()) as Box<UserRolesRepo>
         }
@@ -152,9 +268,85 @@ pub mod tests {
             Box::new(UserRolesRepoMock::default()) as Box<UserRolesRepo>
         }
 
+        fn create_suppressions_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<SuppressionsRepo + 'a> {
+            Box::new(SuppressionsRepoMock::default()) as Box<SuppressionsRepo>
+        }
+
+        fn create_suppressions_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<SuppressionsRepo + 'a> {
+            Box::new(SuppressionsRepoMock::default()) as Box<SuppressionsRepo>
+        }
+
         fn create_templates_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<TemplatesRepo + 'a> {
             Box::new(TemplatesRepoMock::default()) as Box<TemplatesRepo>
         }
+
+        fn create_templates_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<TemplatesRepo + 'a> {
+            Box::new(TemplatesRepoMock::default()) as Box<TemplatesRepo>
+        }
+
+        fn create_template_partials_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<TemplatePartialsRepo + 'a> {
+            Box::new(TemplatePartialsRepoMock::default()) as Box<TemplatePartialsRepo>
+        }
+
+        fn create_template_partials_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<TemplatePartialsRepo + 'a> {
+            Box::new(TemplatePartialsRepoMock::default()) as Box<TemplatePartialsRepo>
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct NotificationPreferencesRepoMock;
+
+    impl NotificationPreferencesRepo for NotificationPreferencesRepoMock {
+        fn list_for_user(&self, _user_id: UserId) -> RepoResult<Vec<NotificationPreference>> {
+            Ok(vec![])
+        }
+
+        fn find(&self, _email: &str, _category: NotificationCategory) -> RepoResult<Option<NotificationPreference>> {
+            Ok(None)
+        }
+
+        fn upsert(&self, payload: NewNotificationPreference) -> RepoResult<NotificationPreference> {
+            Ok(NotificationPreference {
+                id: 1,
+                user_id: payload.user_id,
+                email: payload.email,
+                category: payload.category,
+                enabled: payload.enabled,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn delete(&self, _id: i32) -> RepoResult<Option<NotificationPreference>> {
+            Ok(None)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct SuppressionsRepoMock;
+
+    impl SuppressionsRepo for SuppressionsRepoMock {
+        fn list(&self, _email: Option<&str>, _offset: i64, _count: i64) -> RepoResult<Vec<Suppression>> {
+            Ok(vec![])
+        }
+
+        fn find_any(&self, _emails: &[String]) -> RepoResult<Vec<Suppression>> {
+            Ok(vec![])
+        }
+
+        fn upsert(&self, payload: NewSuppression) -> RepoResult<Suppression> {
+            Ok(Suppression {
+                id: 1,
+                email: payload.email,
+                reason: payload.reason,
+                source: payload.source,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn delete(&self, _email: &str) -> RepoResult<Option<Suppression>> {
+            Ok(None)
+        }
     }
 
     #[derive(Clone, Default)]
@@ -217,21 +409,312 @@ pub mod tests {
     pub struct TemplatesRepoMock;
 
     impl TemplatesRepo for TemplatesRepoMock {
-        fn get_template_by_name(&self, template_name: TemplateVariant) -> RepoResult<Template> {
-            Ok(Template {
+        fn list(&self) -> RepoResult<Vec<Template>> {
+            Ok(vec![])
+        }
+
+        fn content_version(&self) -> RepoResult<ContentVersion> {
+            Ok(ContentVersion::default())
+        }
+
+        fn find_localized(&self, template_name: &str, template_locales: &[String]) -> RepoResult<Option<Template>> {
+            Ok(Some(Template {
                 id: 1,
-                name: template_name,
+                name: template_name.to_string(),
                 data: "<html></html>".to_string(),
+                locale: template_locales.first().cloned().unwrap_or_else(|| "en".to_string()),
+                subject: None,
+                plain_text: None,
+                updated_at: SystemTime::now(),
+            }))
+        }
+
+        fn search(&self, _name_filter: Option<&str>) -> RepoResult<Vec<Template>> {
+            Ok(vec![])
+        }
+
+        fn create(&self, payload: NewTemplate) -> RepoResult<Template> {
+            Ok(Template {
+                id: 1,
+                name: payload.name,
+                data: payload.data,
+                locale: payload.locale,
+                subject: payload.subject,
+                plain_text: payload.plain_text,
+                updated_at: SystemTime::now(),
             })
         }
 
-        fn update(&self, template_name: TemplateVariant, payload: String) -> RepoResult<Template> {
+        fn update(&self, template_name: &str, template_locale: &str, payload: String) -> RepoResult<Template> {
             Ok(Template {
                 id: 1,
-                name: template_name,
+                name: template_name.to_string(),
                 data: payload,
+                locale: template_locale.to_string(),
+                subject: None,
+                plain_text: None,
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn update_subject(&self, template_name: &str, template_locale: &str, template_subject: Option<String>) -> RepoResult<Template> {
+            Ok(Template {
+                id: 1,
+                name: template_name.to_string(),
+                data: "<html></html>".to_string(),
+                locale: template_locale.to_string(),
+                subject: template_subject,
+                plain_text: None,
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn update_plain_text(
+            &self,
+            template_name: &str,
+            template_locale: &str,
+            template_plain_text: Option<String>,
+        ) -> RepoResult<Template> {
+            Ok(Template {
+                id: 1,
+                name: template_name.to_string(),
+                data: "<html></html>".to_string(),
+                locale: template_locale.to_string(),
+                subject: None,
+                plain_text: template_plain_text,
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn delete(&self, template_name: &str) -> RepoResult<Vec<Template>> {
+            Ok(vec![Template {
+                id: 1,
+                name: template_name.to_string(),
+                data: "<html></html>".to_string(),
+                locale: "en".to_string(),
+                subject: None,
+                plain_text: None,
+                updated_at: SystemTime::now(),
+            }])
+        }
+
+        fn create_version(&self, template: &Template, author_id: Option<UserId>) -> RepoResult<TemplateVersion> {
+            Ok(TemplateVersion {
+                id: 1,
+                template_id: template.id,
+                version: 1,
+                data: template.data.clone(),
+                author_id,
+                created_at: SystemTime::now(),
+                subject: template.subject.clone(),
+                plain_text: template.plain_text.clone(),
+            })
+        }
+
+        fn list_versions(&self, _template_name: &str, _template_locale: &str) -> RepoResult<Vec<TemplateVersion>> {
+            Ok(vec![])
+        }
+
+        fn get_version(&self, _template_name: &str, _template_locale: &str, _version: i32) -> RepoResult<Option<TemplateVersion>> {
+            Ok(None)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct TemplatePartialsRepoMock;
+
+    impl TemplatePartialsRepo for TemplatePartialsRepoMock {
+        fn list(&self) -> RepoResult<Vec<TemplatePartial>> {
+            Ok(vec![])
+        }
+
+        fn content_version(&self) -> RepoResult<ContentVersion> {
+            Ok(ContentVersion::default())
+        }
+
+        fn get(&self, _name: &str) -> RepoResult<Option<TemplatePartial>> {
+            Ok(None)
+        }
+
+        fn upsert(&self, payload: NewTemplatePartial) -> RepoResult<TemplatePartial> {
+            Ok(TemplatePartial {
+                id: 1,
+                name: payload.name,
+                data: payload.data,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
             })
         }
+
+        fn delete(&self, _name: &str) -> RepoResult<Option<TemplatePartial>> {
+            Ok(None)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct NotificationsRepoMock;
+
+    impl NotificationsRepo for NotificationsRepoMock {
+        fn create(&self, payload: NewNotification) -> RepoResult<Notification> {
+            Ok(Notification {
+                id: payload.id,
+                recipient: payload.recipient,
+                template: payload.template,
+                subject: payload.subject,
+                status: NotificationStatus::Queued,
+                provider_message_id: None,
+                user_id: payload.user_id,
+                sent_at: None,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+                provider: None,
+            })
+        }
+
+        fn get(&self, _id: Uuid) -> RepoResult<Option<Notification>> {
+            Ok(None)
+        }
+
+        fn search(&self, _search: NotificationsSearch, _offset: i64, _count: i64) -> RepoResult<Vec<Notification>> {
+            Ok(vec![])
+        }
+
+        fn update_status(&self, id: Uuid, status: NotificationStatus) -> RepoResult<Notification> {
+            Ok(Notification {
+                id,
+                recipient: "user@storiqa.com".to_string(),
+                template: None,
+                subject: String::default(),
+                status,
+                provider_message_id: None,
+                user_id: None,
+                sent_at: None,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+                provider: None,
+            })
+        }
+
+        fn mark_sent(&self, id: Uuid, provider: &str) -> RepoResult<Notification> {
+            Ok(Notification {
+                id,
+                recipient: "user@storiqa.com".to_string(),
+                template: None,
+                subject: String::default(),
+                status: NotificationStatus::Sent,
+                provider_message_id: None,
+                user_id: None,
+                sent_at: Some(SystemTime::now()),
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+                provider: Some(provider.to_string()),
+            })
+        }
+
+        fn find_by_provider_message_id(&self, _provider_message_id: &str) -> RepoResult<Option<Notification>> {
+            Ok(None)
+        }
+
+        fn set_provider_message_id(&self, id: Uuid, provider_message_id: &str) -> RepoResult<Notification> {
+            Ok(Notification {
+                id,
+                recipient: "user@storiqa.com".to_string(),
+                template: None,
+                subject: String::default(),
+                status: NotificationStatus::Sent,
+                provider_message_id: Some(provider_message_id.to_string()),
+                user_id: None,
+                sent_at: Some(SystemTime::now()),
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+                provider: Some("sendgrid".to_string()),
+            })
+        }
+
+        fn add_event(&self, payload: NewNotificationEvent) -> RepoResult<Option<NotificationEvent>> {
+            Ok(Some(NotificationEvent {
+                id: 1,
+                notification_id: payload.notification_id,
+                status: payload.status,
+                provider_event_id: payload.provider_event_id,
+                occurred_at: payload.occurred_at,
+                created_at: SystemTime::now(),
+            }))
+        }
+
+        fn engagement_report(&self, _search: EngagementSearch) -> RepoResult<Vec<EngagementStats>> {
+            Ok(vec![])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct OutboxRepoMock;
+
+    impl OutboxRepo for OutboxRepoMock {
+        fn create(&self, payload: NewOutboxMessage) -> RepoResult<OutboxMessage> {
+            Ok(OutboxMessage {
+                id: payload.id,
+                payload: payload.payload,
+                status: payload.status.unwrap_or(OutboxStatus::Pending),
+                attempts: 0,
+                next_attempt_at: payload.next_attempt_at.unwrap_or_else(SystemTime::now),
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn claim_due(&self, _limit: i64, _lease: Duration) -> RepoResult<Vec<OutboxMessage>> {
+            Ok(vec![])
+        }
+
+        fn record_attempt(
+            &self,
+            attempt: NewOutboxAttempt,
+            status: OutboxStatus,
+            next_attempt_at: SystemTime,
+        ) -> RepoResult<OutboxMessage> {
+            Ok(OutboxMessage {
+                id: attempt.message_id,
+                payload: serde_json::Value::Null,
+                status,
+                attempts: 1,
+                next_attempt_at,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn list_attempts(&self, _message_id: Uuid) -> RepoResult<Vec<OutboxAttempt>> {
+            Ok(vec![])
+        }
+
+        fn get(&self, _message_id: Uuid) -> RepoResult<Option<OutboxMessage>> {
+            Ok(None)
+        }
+
+        fn list_by_status(&self, _status: OutboxStatus, _offset: i64, _count: i64) -> RepoResult<Vec<OutboxMessage>> {
+            Ok(vec![])
+        }
+
+        fn requeue(&self, _message_id: Uuid) -> RepoResult<Option<OutboxMessage>> {
+            Ok(None)
+        }
+
+        fn release_scheduled(&self) -> RepoResult<Vec<OutboxMessage>> {
+            Ok(vec![])
+        }
+
+        fn cancel(&self, _message_id: Uuid) -> RepoResult<Option<OutboxMessage>> {
+            Ok(None)
+        }
+
+        fn find_by_idempotency_key(&self, _key: &str, _scope: &str, _since: SystemTime) -> RepoResult<Option<OutboxMessage>> {
+            Ok(None)
+        }
+
+        fn save_idempotency_key(&self, _payload: NewIdempotencyKey) -> RepoResult<()> {
+            Ok(())
+        }
     }
 
     #[derive(Default)]
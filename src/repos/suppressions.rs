@@ -0,0 +1,129 @@
+//! Repo for suppressions table. Suppression is an address emails are never
+//! sent to, e.g. because it hard-bounced or its owner complained about spam.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::upsert::excluded;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewSuppression, Suppression};
+use repos::legacy_acl::*;
+use schema::suppressions::dsl as Suppressions;
+
+/// Suppressions repository
+pub trait SuppressionsRepo {
+    /// List suppressions, newest first
+    fn list(&self, email: Option<&str>, offset: i64, count: i64) -> RepoResult<Vec<Suppression>>;
+
+    /// Suppressions of any of the addresses
+    fn find_any(&self, emails: &[String]) -> RepoResult<Vec<Suppression>>;
+
+    /// Suppress the address, reason and source of the existing suppression are replaced
+    fn upsert(&self, payload: NewSuppression) -> RepoResult<Suppression>;
+
+    /// Remove the address from the list
+    fn delete(&self, email: &str) -> RepoResult<Option<Suppression>>;
+}
+
+/// Implementation of Suppressions trait
+pub struct SuppressionsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Suppression>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SuppressionsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Suppression>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SuppressionsRepo
+    for SuppressionsRepoImpl<'a, T>
+{
+    fn list(&self, email: Option<&str>, offset: i64, count: i64) -> RepoResult<Vec<Suppression>> {
+        debug!("List suppressions of {:?}, offset {}, count {}.", email, offset, count);
+        let mut query = Suppressions::suppressions.into_boxed();
+        if let Some(email) = email {
+            query = query.filter(Suppressions::email.eq(email));
+        }
+
+        query
+            .order(Suppressions::created_at.desc())
+            .offset(offset)
+            .limit(count)
+            .get_results::<Suppression>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|suppressions| {
+                for suppression in &suppressions {
+                    acl::check(&*self.acl, Resource::Suppressions, Action::Read, self, Some(suppression))?;
+                }
+                Ok(suppressions)
+            })
+            .map_err(|e: FailureError| e.context(format!("List suppressions of {:?} error occurred", email)).into())
+    }
+
+    fn find_any(&self, emails: &[String]) -> RepoResult<Vec<Suppression>> {
+        debug!("Find suppressions of {:?}.", emails);
+        Suppressions::suppressions
+            .filter(Suppressions::email.eq_any(emails))
+            .get_results::<Suppression>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|suppressions| {
+                for suppression in &suppressions {
+                    acl::check(&*self.acl, Resource::Suppressions, Action::Read, self, Some(suppression))?;
+                }
+                Ok(suppressions)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find suppressions of {:?} error occurred", emails)).into())
+    }
+
+    fn upsert(&self, payload: NewSuppression) -> RepoResult<Suppression> {
+        debug!("Upsert suppression {:?}.", payload);
+        acl::check(&*self.acl, Resource::Suppressions, Action::Create, self, None)
+            .and_then(|_| {
+                diesel::insert_into(Suppressions::suppressions)
+                    .values(&payload)
+                    .on_conflict(Suppressions::email)
+                    .do_update()
+                    .set((
+                        Suppressions::reason.eq(excluded(Suppressions::reason)),
+                        Suppressions::source.eq(excluded(Suppressions::source)),
+                    ))
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Upsert suppression {:?} error occurred", payload)).into())
+    }
+
+    fn delete(&self, email: &str) -> RepoResult<Option<Suppression>> {
+        debug!("Delete suppression of {}.", email);
+        acl::check(&*self.acl, Resource::Suppressions, Action::Delete, self, None)
+            .and_then(|_| {
+                diesel::delete(Suppressions::suppressions.filter(Suppressions::email.eq(email)))
+                    .get_result(self.db_conn)
+                    .optional()
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete suppression of {} error occurred", email)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Suppression>
+    for SuppressionsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&Suppression>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Repo for template_partials table. Partial is a piece of markup shared
+//! by templates, e.g. the base layout.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::upsert::excluded;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{ContentVersion, NewTemplatePartial, TemplatePartial};
+use repos::legacy_acl::*;
+use schema::template_partials::dsl as TemplatePartials;
+
+/// Template partials repository
+pub trait TemplatePartialsRepo {
+    /// List all partials
+    fn list(&self) -> RepoResult<Vec<TemplatePartial>>;
+
+    /// Count of partials and the time the latest of them was changed
+    fn content_version(&self) -> RepoResult<ContentVersion>;
+
+    /// Get partial by name
+    fn get(&self, name: &str) -> RepoResult<Option<TemplatePartial>>;
+
+    /// Create partial or replace content of the existing one
+    fn upsert(&self, payload: NewTemplatePartial) -> RepoResult<TemplatePartial>;
+
+    /// Delete partial by name
+    fn delete(&self, name: &str) -> RepoResult<Option<TemplatePartial>>;
+}
+
+/// Implementation of TemplatePartials trait
+pub struct TemplatePartialsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, TemplatePartial>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> TemplatePartialsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, TemplatePartial>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> TemplatePartialsRepo
+    for TemplatePartialsRepoImpl<'a, T>
+{
+    fn list(&self) -> RepoResult<Vec<TemplatePartial>> {
+        debug!("List template partials.");
+        TemplatePartials::template_partials
+            .order(TemplatePartials::name)
+            .get_results::<TemplatePartial>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|partials| {
+                for partial in &partials {
+                    acl::check(&*self.acl, Resource::Templates, Action::Read, self, Some(partial))?;
+                }
+                Ok(partials)
+            })
+            .map_err(|e: FailureError| e.context("List template partials error occurred").into())
+    }
+
+    fn content_version(&self) -> RepoResult<ContentVersion> {
+        debug!("Get content version of template partials.");
+        acl::check(&*self.acl, Resource::Templates, Action::Read, self, None)
+            .and_then(|_| {
+                TemplatePartials::template_partials
+                    .select((diesel::dsl::count_star(), diesel::dsl::max(TemplatePartials::updated_at)))
+                    .get_result::<ContentVersion>(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context("Get content version of template partials error occurred").into())
+    }
+
+    fn get(&self, name: &str) -> RepoResult<Option<TemplatePartial>> {
+        debug!("Get template partial {}.", name);
+        TemplatePartials::template_partials
+            .filter(TemplatePartials::name.eq(name))
+            .get_result::<TemplatePartial>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|partial| {
+                if let Some(ref partial) = partial {
+                    acl::check(&*self.acl, Resource::Templates, Action::Read, self, Some(partial))?;
+                }
+                Ok(partial)
+            })
+            .map_err(|e: FailureError| e.context(format!("Get template partial {} error occurred", name)).into())
+    }
+
+    fn upsert(&self, payload: NewTemplatePartial) -> RepoResult<TemplatePartial> {
+        debug!("Upsert template partial {:?}.", payload);
+        acl::check(&*self.acl, Resource::Templates, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::insert_into(TemplatePartials::template_partials)
+                    .values(&payload)
+                    .on_conflict(TemplatePartials::name)
+                    .do_update()
+                    .set(TemplatePartials::data.eq(excluded(TemplatePartials::data)))
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Upsert template partial {:?} error occurred", payload)).into())
+    }
+
+    fn delete(&self, name: &str) -> RepoResult<Option<TemplatePartial>> {
+        debug!("Delete template partial {}.", name);
+        acl::check(&*self.acl, Resource::Templates, Action::Delete, self, None)
+            .and_then(|_| {
+                diesel::delete(TemplatePartials::template_partials.filter(TemplatePartials::name.eq(name)))
+                    .get_result(self.db_conn)
+                    .optional()
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete template partial {} error occurred", name)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, TemplatePartial>
+    for TemplatePartialsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&TemplatePartial>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
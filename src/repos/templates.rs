@@ -13,20 +13,50 @@ use failure::Error as FailureError;
 use super::acl;
 use super::types::RepoResult;
 use models::authorization::*;
-use models::Template;
+use models::{ContentVersion, NewTemplate, NewTemplateVersion, Template, TemplateVersion};
 use repos::legacy_acl::*;
-use stq_static_resources::TemplateVariant;
 use stq_types::UserId;
 
+use schema::template_versions::dsl as TemplateVersions;
 use schema::templates::dsl::*;
 
 /// Templates repository for handling Templates
 pub trait TemplatesRepo {
-    /// Get template by name
-    fn get_template_by_name(&self, template: TemplateVariant) -> RepoResult<Template>;
+    /// Get template in the first of `template_locales` it exists in
+    fn find_localized(&self, template_name: &str, template_locales: &[String]) -> RepoResult<Option<Template>>;
+
+    /// List templates in all locales
+    fn list(&self) -> RepoResult<Vec<Template>>;
+
+    /// Count of templates in all locales and the time the latest of them was changed
+    fn content_version(&self) -> RepoResult<ContentVersion>;
+
+    /// List templates in all locales with names containing `name_filter`
+    fn search(&self, name_filter: Option<&str>) -> RepoResult<Vec<Template>>;
+
+    /// Create template
+    fn create(&self, payload: NewTemplate) -> RepoResult<Template>;
 
     /// Update template
-    fn update(&self, temlate_name: TemplateVariant, payload: String) -> RepoResult<Template>;
+    fn update(&self, template_name: &str, template_locale: &str, payload: String) -> RepoResult<Template>;
+
+    /// Set subject template, `None` keeps subjects given by callers
+    fn update_subject(&self, template_name: &str, template_locale: &str, template_subject: Option<String>) -> RepoResult<Template>;
+
+    /// Set plain text template, `None` generates plain text from HTML
+    fn update_plain_text(&self, template_name: &str, template_locale: &str, template_plain_text: Option<String>) -> RepoResult<Template>;
+
+    /// Delete template in all locales together with its versions
+    fn delete(&self, template_name: &str) -> RepoResult<Vec<Template>>;
+
+    /// Record current content of the template as its next version
+    fn create_version(&self, template: &Template, author_id: Option<UserId>) -> RepoResult<TemplateVersion>;
+
+    /// List versions of the template, newest first
+    fn list_versions(&self, template_name: &str, template_locale: &str) -> RepoResult<Vec<TemplateVersion>>;
+
+    /// Get specific version of the template
+    fn get_version(&self, template_name: &str, template_locale: &str, version: i32) -> RepoResult<Option<TemplateVersion>>;
 }
 
 /// Implementation of Templates trait
@@ -46,30 +76,267 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> TemplatesRepo for TemplatesRepoImpl<'a, T> {
-    fn get_template_by_name(&self, template_name: TemplateVariant) -> RepoResult<Template> {
-        debug!("get template by name {:?}.", template_name);
-        self.execute_query(templates.filter(name.eq(template_name.clone())))
-            .and_then(|template| acl::check(&*self.acl, Resource::Templates, Action::Read, self, Some(&template)).map(|_| template))
-            .map_err(|e: FailureError| e.context(format!("Getting template with name {:?} failed.", template_name)).into())
-    }
-
-    fn update(&self, template_name: TemplateVariant, payload: String) -> RepoResult<Template> {
-        debug!("Updating template with name {:?} and payload {}.", template_name, payload);
-        self.execute_query(templates.filter(name.eq(template_name.clone())))
-            .and_then(|template| acl::check(&*self.acl, Resource::Templates, Action::Update, self, Some(&template)))
-            .and_then(|_| {
-                let filter = templates.filter(name.eq(template_name.clone()));
-                let query = diesel::update(filter).set(data.eq(&payload));
-                query.get_result(self.db_conn).map_err(From::from)
+    fn find_localized(&self, template_name: &str, template_locales: &[String]) -> RepoResult<Option<Template>> {
+        debug!("find template by name {:?} in locales {:?}.", template_name, template_locales);
+        templates
+            .filter(name.eq(template_name))
+            .filter(locale.eq_any(template_locales))
+            .get_results::<Template>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|mut found| {
+                let position = template_locales
+                    .iter()
+                    .filter_map(|template_locale| found.iter().position(|template| &template.locale == template_locale))
+                    .next();
+                let template = position.map(|position| found.swap_remove(position));
+                if let Some(ref template) = template {
+                    acl::check(&*self.acl, Resource::Templates, Action::Read, self, Some(template))?;
+                }
+                Ok(template)
             })
             .map_err(|e: FailureError| {
                 e.context(format!(
-                    "Updating template with name {:?} and payload {} failed.",
-                    template_name, payload
+                    "Finding template with name {:?} in locales {:?} failed.",
+                    template_name, template_locales
                 ))
                 .into()
             })
     }
+
+    fn list(&self) -> RepoResult<Vec<Template>> {
+        debug!("List templates.");
+        templates
+            .order((name, locale))
+            .get_results::<Template>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|found| {
+                for template in &found {
+                    acl::check(&*self.acl, Resource::Templates, Action::Read, self, Some(template))?;
+                }
+                Ok(found)
+            })
+            .map_err(|e: FailureError| e.context("List templates error occurred").into())
+    }
+
+    fn content_version(&self) -> RepoResult<ContentVersion> {
+        debug!("Get content version of templates.");
+        acl::check(&*self.acl, Resource::Templates, Action::Read, self, None)
+            .and_then(|_| {
+                templates
+                    .select((diesel::dsl::count_star(), diesel::dsl::max(updated_at)))
+                    .get_result::<ContentVersion>(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context("Get content version of templates error occurred").into())
+    }
+
+    fn search(&self, name_filter: Option<&str>) -> RepoResult<Vec<Template>> {
+        debug!("Search templates by name {:?}.", name_filter);
+        let mut query = templates.into_boxed();
+        if let Some(name_filter) = name_filter {
+            query = query.filter(name.like(format!("%{}%", escape_like(name_filter))));
+        }
+
+        query
+            .order((name, locale))
+            .get_results::<Template>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|found| {
+                for template in &found {
+                    acl::check(&*self.acl, Resource::Templates, Action::Read, self, Some(template))?;
+                }
+                Ok(found)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Search templates by name {:?} error occurred", name_filter))
+                    .into()
+            })
+    }
+
+    fn create(&self, payload: NewTemplate) -> RepoResult<Template> {
+        debug!("Creating template {:?}.", payload);
+        diesel::insert_into(templates)
+            .values(&payload)
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|template| acl::check(&*self.acl, Resource::Templates, Action::Create, self, Some(&template)).map(|_| template))
+            .map_err(|e: FailureError| e.context(format!("Creating template {:?} failed.", payload)).into())
+    }
+
+    fn update(&self, template_name: &str, template_locale: &str, payload: String) -> RepoResult<Template> {
+        debug!(
+            "Updating template with name {:?}, locale {} and payload {}.",
+            template_name, template_locale, payload
+        );
+        self.execute_query(
+            templates
+                .filter(name.eq(template_name))
+                .filter(locale.eq(template_locale.to_string())),
+        )
+        .and_then(|template: Template| acl::check(&*self.acl, Resource::Templates, Action::Update, self, Some(&template)).map(|_| template))
+        .and_then(|template| {
+            let filter = templates.filter(id.eq(template.id));
+            let query = diesel::update(filter).set(data.eq(&payload));
+            query.get_result(self.db_conn).map_err(From::from)
+        })
+        .map_err(|e: FailureError| {
+            e.context(format!(
+                "Updating template with name {:?}, locale {} and payload {} failed.",
+                template_name, template_locale, payload
+            ))
+            .into()
+        })
+    }
+
+    fn update_subject(&self, template_name: &str, template_locale: &str, template_subject: Option<String>) -> RepoResult<Template> {
+        debug!(
+            "Updating subject of template with name {:?} and locale {} to {:?}.",
+            template_name, template_locale, template_subject
+        );
+        self.execute_query(
+            templates
+                .filter(name.eq(template_name))
+                .filter(locale.eq(template_locale.to_string())),
+        )
+        .and_then(|template: Template| acl::check(&*self.acl, Resource::Templates, Action::Update, self, Some(&template)).map(|_| template))
+        .and_then(|template| {
+            let filter = templates.filter(id.eq(template.id));
+            let query = diesel::update(filter).set(subject.eq(&template_subject));
+            query.get_result(self.db_conn).map_err(From::from)
+        })
+        .map_err(|e: FailureError| {
+            e.context(format!(
+                "Updating subject of template with name {:?} and locale {} failed.",
+                template_name, template_locale
+            ))
+            .into()
+        })
+    }
+
+    fn update_plain_text(&self, template_name: &str, template_locale: &str, template_plain_text: Option<String>) -> RepoResult<Template> {
+        debug!(
+            "Updating plain text of template with name {:?} and locale {} to {:?}.",
+            template_name, template_locale, template_plain_text
+        );
+        self.execute_query(
+            templates
+                .filter(name.eq(template_name))
+                .filter(locale.eq(template_locale.to_string())),
+        )
+        .and_then(|template: Template| acl::check(&*self.acl, Resource::Templates, Action::Update, self, Some(&template)).map(|_| template))
+        .and_then(|template| {
+            let filter = templates.filter(id.eq(template.id));
+            let query = diesel::update(filter).set(plain_text.eq(&template_plain_text));
+            query.get_result(self.db_conn).map_err(From::from)
+        })
+        .map_err(|e: FailureError| {
+            e.context(format!(
+                "Updating plain text of template with name {:?} and locale {} failed.",
+                template_name, template_locale
+            ))
+            .into()
+        })
+    }
+
+    fn delete(&self, template_name: &str) -> RepoResult<Vec<Template>> {
+        debug!("Delete template with name {:?}.", template_name);
+        templates
+            .filter(name.eq(template_name))
+            .get_results::<Template>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|found| {
+                for template in &found {
+                    acl::check(&*self.acl, Resource::Templates, Action::Delete, self, Some(template))?;
+                }
+                let filtered = templates.filter(name.eq(template_name));
+                diesel::delete(filtered).get_results(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete template with name {:?} error occurred", template_name))
+                    .into()
+            })
+    }
+
+    fn create_version(&self, template: &Template, author_id: Option<UserId>) -> RepoResult<TemplateVersion> {
+        debug!("Create version of template {:?} by {:?}.", template.name, author_id);
+        acl::check(&*self.acl, Resource::Templates, Action::Update, self, Some(template))
+            .and_then(|_| {
+                TemplateVersions::template_versions
+                    .filter(TemplateVersions::template_id.eq(template.id))
+                    .select(diesel::dsl::max(TemplateVersions::version))
+                    .first::<Option<i32>>(self.db_conn)
+                    .map_err(From::from)
+            })
+            .and_then(|last_version| {
+                let payload = NewTemplateVersion {
+                    template_id: template.id,
+                    version: last_version.unwrap_or(0) + 1,
+                    data: template.data.clone(),
+                    author_id,
+                    subject: template.subject.clone(),
+                    plain_text: template.plain_text.clone(),
+                };
+                diesel::insert_into(TemplateVersions::template_versions)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create version of template {:?} error occurred", template.name))
+                    .into()
+            })
+    }
+
+    fn list_versions(&self, template_name: &str, template_locale: &str) -> RepoResult<Vec<TemplateVersion>> {
+        debug!("List versions of template {:?} in locale {}.", template_name, template_locale);
+        self.execute_query(
+            templates
+                .filter(name.eq(template_name))
+                .filter(locale.eq(template_locale.to_string())),
+        )
+        .and_then(|template: Template| {
+            acl::check(&*self.acl, Resource::Templates, Action::Read, self, Some(&template))?;
+            TemplateVersions::template_versions
+                .filter(TemplateVersions::template_id.eq(template.id))
+                .order(TemplateVersions::version.desc())
+                .get_results(self.db_conn)
+                .map_err(From::from)
+        })
+        .map_err(|e: FailureError| {
+            e.context(format!("List versions of template {:?} error occurred", template_name))
+                .into()
+        })
+    }
+
+    fn get_version(&self, template_name: &str, template_locale: &str, version: i32) -> RepoResult<Option<TemplateVersion>> {
+        debug!(
+            "Get version {} of template {:?} in locale {}.",
+            version, template_name, template_locale
+        );
+        self.execute_query(
+            templates
+                .filter(name.eq(template_name))
+                .filter(locale.eq(template_locale.to_string())),
+        )
+        .and_then(|template: Template| {
+            acl::check(&*self.acl, Resource::Templates, Action::Read, self, Some(&template))?;
+            TemplateVersions::template_versions
+                .filter(TemplateVersions::template_id.eq(template.id))
+                .filter(TemplateVersions::version.eq(version))
+                .get_result(self.db_conn)
+                .optional()
+                .map_err(From::from)
+        })
+        .map_err(|e: FailureError| {
+            e.context(format!("Get version {} of template {:?} error occurred", version, template_name))
+                .into()
+        })
+    }
+}
+
+/// Template names may contain `_`, which is a wildcard in `LIKE` patterns
+fn escape_like(pattern: &str) -> String {
+    pattern.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Template>
@@ -82,3 +349,15 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("order_create"), "order\\_create");
+        assert_eq!(escape_like("100%"), "100\\%");
+        assert_eq!(escape_like("a\\b"), "a\\\\b");
+    }
+}
@@ -1,8 +1,116 @@
+table! {
+    idempotency_keys (key, scope) {
+        key -> Varchar,
+        scope -> Varchar,
+        message_id -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    notification_events (id) {
+        id -> Int4,
+        notification_id -> Uuid,
+        status -> Varchar,
+        provider_event_id -> Nullable<Varchar>,
+        occurred_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    notification_preferences (id) {
+        id -> Int4,
+        user_id -> Nullable<Int4>,
+        email -> Varchar,
+        category -> Varchar,
+        enabled -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    notifications (id) {
+        id -> Uuid,
+        recipient -> Varchar,
+        template -> Nullable<Varchar>,
+        subject -> Varchar,
+        status -> Varchar,
+        provider_message_id -> Nullable<Varchar>,
+        user_id -> Nullable<Int4>,
+        sent_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        provider -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    outbox_attempts (id) {
+        id -> Int4,
+        message_id -> Uuid,
+        succeeded -> Bool,
+        error -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        provider -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    outbox_messages (id) {
+        id -> Uuid,
+        payload -> Jsonb,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    suppressions (id) {
+        id -> Int4,
+        email -> Varchar,
+        reason -> Varchar,
+        source -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    template_partials (id) {
+        id -> Int4,
+        name -> Varchar,
+        data -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    template_versions (id) {
+        id -> Int4,
+        template_id -> Int4,
+        version -> Int4,
+        data -> Varchar,
+        author_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+        subject -> Nullable<Varchar>,
+        plain_text -> Nullable<Varchar>,
+    }
+}
+
 table! {
     templates (id) {
         id -> Int4,
         name -> Varchar,
         data -> Varchar,
+        locale -> Varchar,
+        subject -> Nullable<Varchar>,
+        plain_text -> Nullable<Varchar>,
+        updated_at -> Timestamp,
     }
 }
 
@@ -17,7 +125,21 @@ table! {
     }
 }
 
+joinable!(idempotency_keys -> outbox_messages (message_id));
+joinable!(notification_events -> notifications (notification_id));
+joinable!(outbox_attempts -> outbox_messages (message_id));
+joinable!(template_versions -> templates (template_id));
+
 allow_tables_to_appear_in_same_query!(
+    idempotency_keys,
+    notification_events,
+    notification_preferences,
+    notifications,
+    outbox_attempts,
+    outbox_messages,
+    suppressions,
+    template_partials,
+    template_versions,
     templates,
     user_roles,
 );
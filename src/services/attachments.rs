@@ -0,0 +1,168 @@
+//! Checks attachments of the emails before they are queued: content encoding,
+//! file names, MIME types and size limits.
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use base64;
+use mime::Mime;
+use validator::{ValidationError, ValidationErrors};
+
+use config::AttachmentsConf;
+use models::Attachment;
+
+/// Decodes base64 content of the attachment, line breaks are allowed
+pub fn decode_attachment(attachment: &Attachment) -> Result<Vec<u8>, base64::DecodeError> {
+    let content = attachment.content.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    base64::decode(&content)
+}
+
+/// Validates attachments against the limits, errors are keyed by `attachments`
+pub fn validate_attachments(attachments: &[Attachment], conf: &AttachmentsConf) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if attachments.len() > conf.max_count {
+        errors.add(
+            "attachments",
+            error("count", format!("At most {} attachments are allowed", conf.max_count)),
+        );
+    }
+
+    let mut total_size = 0;
+    for attachment in attachments {
+        let filename = &attachment.filename;
+        if filename.trim().is_empty() || filename.chars().any(|c| c.is_control() || c == '/' || c == '\\' || c == '"') {
+            errors.add("attachments", error("filename", format!("Invalid file name {:?}", filename)));
+        }
+        if Mime::from_str(&attachment.mime_type).is_err() {
+            errors.add(
+                "attachments",
+                error("type", format!("Invalid MIME type {} of {}", attachment.mime_type, filename)),
+            );
+        }
+        if let Some(ref content_id) = attachment.content_id {
+            if content_id.is_empty() || !content_id.chars().all(|c| c.is_ascii_alphanumeric() || "._@-".contains(c)) {
+                errors.add(
+                    "attachments",
+                    error("content_id", format!("Invalid content id {:?} of {}", content_id, filename)),
+                );
+            }
+        }
+        match decode_attachment(attachment) {
+            Ok(content) => {
+                if content.len() > conf.max_size_bytes {
+                    errors.add(
+                        "attachments",
+                        error("size", format!("{} exceeds {} bytes", filename, conf.max_size_bytes)),
+                    );
+                }
+                total_size += content.len();
+            }
+            Err(_) => errors.add(
+                "attachments",
+                error("content", format!("Content of {} is not valid base64", filename)),
+            ),
+        }
+    }
+
+    if total_size > conf.max_total_size_bytes {
+        errors.add(
+            "attachments",
+            error(
+                "total_size",
+                format!("Attachments exceed {} bytes in total", conf.max_total_size_bytes),
+            ),
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn error(code: &'static str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conf() -> AttachmentsConf {
+        AttachmentsConf {
+            max_count: 2,
+            max_size_bytes: 8,
+            max_total_size_bytes: 12,
+        }
+    }
+
+    fn attachment(filename: &str, content: &[u8]) -> Attachment {
+        Attachment {
+            content: base64::encode(content),
+            filename: filename.to_string(),
+            mime_type: "application/pdf".to_string(),
+            content_id: None,
+        }
+    }
+
+    fn codes(result: Result<(), ValidationErrors>) -> Vec<String> {
+        result
+            .unwrap_err()
+            .inner()
+            .get("attachments")
+            .map(|errors| errors.iter().map(|error| error.code.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_valid_attachments() {
+        let mut logo = attachment("logo.png", b"png");
+        logo.mime_type = "image/png".to_string();
+        logo.content_id = Some("logo@storiqa.com".to_string());
+        assert!(validate_attachments(&[attachment("invoice.pdf", b"invoice"), logo], &conf()).is_ok());
+        assert!(validate_attachments(&[], &conf()).is_ok());
+    }
+
+    #[test]
+    fn test_content_with_line_breaks() {
+        let mut invoice = attachment("invoice.pdf", b"invoice");
+        invoice.content = format!("{}\r\n{}", &invoice.content[..4], &invoice.content[4..]);
+        assert_eq!(decode_attachment(&invoice).unwrap(), b"invoice".to_vec());
+    }
+
+    #[test]
+    fn test_size_limits() {
+        assert_eq!(
+            codes(validate_attachments(&[attachment("big.pdf", b"123456789")], &conf())),
+            vec!["size"]
+        );
+        assert_eq!(
+            codes(validate_attachments(
+                &[attachment("a.pdf", b"1234567"), attachment("b.pdf", b"1234567")],
+                &conf()
+            )),
+            vec!["total_size"]
+        );
+        assert_eq!(
+            codes(validate_attachments(
+                &[attachment("a.pdf", b"1"), attachment("b.pdf", b"2"), attachment("c.pdf", b"3")],
+                &conf()
+            )),
+            vec!["count"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_attachment() {
+        let mut invalid = attachment("../invoice.pdf", b"invoice");
+        invalid.content = "not base64!".to_string();
+        invalid.mime_type = "pdf".to_string();
+        invalid.content_id = Some("<logo>".to_string());
+        assert_eq!(
+            codes(validate_attachments(&[invalid], &conf())),
+            vec!["filename", "type", "content_id", "content"]
+        );
+    }
+}
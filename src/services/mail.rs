@@ -1,9 +1,13 @@
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
 use failure::Error as FailureError;
 use failure::Fail;
+use futures::future;
 use futures::prelude::*;
-use handlebars::Handlebars;
 use mime::{TEXT_HTML, TEXT_PLAIN};
 use serde::Serialize;
+use serde_json::{self, Value};
 
 use stq_static_resources::*;
 
@@ -11,24 +15,59 @@ use diesel::connection::AnsiTransactionManager;
 use diesel::pg::Pg;
 use diesel::Connection;
 use r2d2::ManageConnection;
+use stq_types::UserId;
+use validator::{ValidationError, ValidationErrors};
 
 use super::types::ServiceFuture;
-use config::SendGridConf;
-use models::SendGridPayload;
+use config::{SendGridConf, UnsubscribeConf};
+use errors::{validation_details, Error};
+use models::{
+    normalize_email, BatchMail, BatchRecipient, BatchRecipientResult, BatchRecipientStatus, LocalizedMail, MailOptions,
+    NotificationCategory, OutboxMessage, OutboxStatus, OutgoingEmail, QueuedEmail, SendGridPayload, SimpleMailPayload, TemplateMail,
+};
 use repos::ReposFactory;
+use services::attachments::validate_attachments;
+use services::outbox::{enqueue_once, idempotency_scope, OutboxService};
+use services::recipients::validate_recipients;
+use services::scheduler::validate_send_at;
+use services::template_registry::CompiledTemplates;
+use services::templates::locale_fallbacks;
+use services::unsubscribe::unsubscribe_url;
 use services::Service;
 
+/// Idempotency scope of the emails sent with `/simple-mail`
+const SIMPLE_MAIL_SCOPE: &'static str = "simple_mail";
+
 pub trait MailService<E>
 where
     E: Email + Serialize + Clone + 'static + Send,
 {
-    /// Send email fro template
-    fn send_email_with_template(self, template_name: TemplateVariant, mail: E) -> Box<Future<Item = (), Error = FailureError> + Send>;
+    /// Send email fro template. Template is looked up in `locale`, then in the request locales and the default one.
+    /// Returns the queued emails, recipients who disabled emails of the template category are skipped.
+    fn send_email_with_template(
+        self,
+        template_name: TemplateVariant,
+        mail: LocalizedMail<E>,
+    ) -> Box<Future<Item = Vec<QueuedEmail>, Error = FailureError> + Send>;
+}
+
+pub trait TemplateMailService {
+    /// Send email from the template by name with arbitrary data, custom templates have no typed endpoints
+    fn send_template_mail(self, template_name: String, mail: TemplateMail) -> ServiceFuture<Vec<QueuedEmail>>;
+}
+
+pub trait BatchMailService {
+    /// Render the template for every recipient with their own context and queue the emails,
+    /// one outbox message per recipient. Failed recipients don't stop the rest of the batch.
+    /// Recipients aren't grouped into SendGrid personalizations: their emails are rendered from
+    /// their own context and locale, which substitutions can't express, the SMTP transport has no
+    /// personalizations, and retries, suppressions, webhook events and cancelling are per message.
+    fn send_batch_mail(self, template_name: String, batch: BatchMail) -> ServiceFuture<Vec<BatchRecipientResult>>;
 }
 
 pub trait SimpleMailService {
     /// Send simple mail
-    fn send_mail(self, mail: SimpleMail) -> ServiceFuture<()>;
+    fn send_mail(self, mail: SimpleMailPayload) -> ServiceFuture<QueuedEmail>;
 }
 
 impl<T, M, F, E> MailService<E> for Service<T, M, F>
@@ -38,67 +77,546 @@ where
     F: ReposFactory<T>,
     E: Email + Serialize + Clone + 'static + Send,
 {
-    fn send_email_with_template(self, template_name: TemplateVariant, mail: E) -> Box<Future<Item = (), Error = FailureError> + Send> {
-        let SendGridConf { from_email, from_name, .. } = self.static_context.config.sendgrid.clone();
+    fn send_email_with_template(
+        self,
+        template_name: TemplateVariant,
+        mail: LocalizedMail<E>,
+    ) -> Box<Future<Item = Vec<QueuedEmail>, Error = FailureError> + Send> {
+        let LocalizedMail {
+            mail,
+            recipients,
+            locale,
+            options,
+        } = mail;
+        let send_mail = mail.clone().into_send_mail();
+        Box::new(
+            self.enqueue_template_email(template_name.to_string(), send_mail, recipients, mail, locale, options)
+                .map_err(|e: FailureError| e.context("Mail service, send_email_with_template endpoint error occured.").into()),
+        )
+    }
+}
 
-        let sendgrid_service = self.static_context.sendgrid_service.clone();
-        let handlebars = Handlebars::new();
+impl<T, M, F> TemplateMailService for Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    fn send_template_mail(self, template_name: String, mail: TemplateMail) -> ServiceFuture<Vec<QueuedEmail>> {
+        let TemplateMail {
+            to,
+            subject,
+            context,
+            locale,
+            options,
+        } = mail;
+        let mut to = to.into_vec().into_iter();
+        let send_mail = SimpleMail {
+            to: to.next().unwrap_or_default(),
+            subject,
+            text: String::default(),
+        };
+        Box::new(
+            self.enqueue_template_email(template_name, send_mail, to.collect(), context, locale, options)
+                .map_err(|e: FailureError| e.context("Mail service, send_template_mail endpoint error occured.").into()),
+        )
+    }
+}
 
+impl<T, M, F> BatchMailService for Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    fn send_batch_mail(self, template_name: String, batch: BatchMail) -> ServiceFuture<Vec<BatchRecipientResult>> {
+        if let Err(errors) = validate_send_at(batch.send_at, &self.static_context.config.scheduler) {
+            return Box::new(future::err(
+                format_err!("Invalid send time of {} batch: {}", template_name, validation_details(&errors))
+                    .context(Error::Parse)
+                    .into(),
+            ));
+        }
+        let max_recipients = self.static_context.config.mail.batch_max_recipients;
+        if batch.recipients.is_empty() || batch.recipients.len() > max_recipients {
+            let mut errors = ValidationErrors::new();
+            let mut error = ValidationError::new(if batch.recipients.is_empty() { "required" } else { "count" });
+            error.message = Some(format!("From 1 to {} recipients are allowed", max_recipients).into());
+            errors.add("recipients", error);
+            return Box::new(future::err(
+                format_err!("Invalid recipients of {} batch: {}", template_name, validation_details(&errors))
+                    .context(Error::Parse)
+                    .into(),
+            ));
+        }
+
+        let BatchMail {
+            subject,
+            context,
+            locale,
+            recipients,
+            send_at,
+        } = batch;
+        let default_locale = self.static_context.config.templates.default_locale.clone();
+        let request_locales = locale.into_iter().chain(self.dynamic_context.locales.clone()).collect::<Vec<_>>();
+        let sender = self.template_sender();
         let repo_factory = self.static_context.repo_factory.clone();
-        let user_id = self.dynamic_context.user_id;
+        let template_registry = self.static_context.template_registry.clone();
 
-        Box::new(
-            self.spawn_on_pool(move |conn| {
-                let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
-                templates_repo
-                    .get_template_by_name(template_name)
-                    .and_then({
-                        let mail = mail.clone();
-                        move |template| {
-                            handlebars
-                                .render_template(&template.data, &mail)
-                                .map_err(move |e| e.context(format!("Couldn't render template {:?}", template.name)).into())
-                        }
-                    })
-                    .map(move |text| {
-                        let mut send_mail = mail.into_send_mail();
-                        send_mail.text = text;
-                        SendGridPayload::from_send_mail(send_mail, from_email.clone(), from_name.clone(), TEXT_HTML)
-                    })
-            })
-            .map_err(|e: FailureError| e.context("Mail service, send_email_with_template endpoint error occured.").into())
-            .and_then(move |payload| {
-                let to = payload.get_address_list().join(", ");
-                debug!("Sending email - to: {}, subject: {}", to, payload.subject);
-                info!("Sending email - template: {:?}, to: {}", template_name, to);
-                sendgrid_service
-                    .send(payload)
-                    .map_err(|e| e.context("SendgridService failed").into())
-            }),
-        )
+        self.spawn_on_pool(move |conn| {
+            let compiled = template_registry.get_or_build(
+                &*repo_factory.create_templates_repo_with_sys_acl(&*conn),
+                &*repo_factory.create_template_partials_repo_with_sys_acl(&*conn),
+            )?;
+            info!("Queueing batch - template: {}, recipients: {}", template_name, recipients.len());
+            let results = recipients
+                .into_iter()
+                .map(|recipient| {
+                    let BatchRecipient {
+                        to,
+                        context: recipient_context,
+                        locale,
+                    } = recipient;
+                    let requested_locales = locale.into_iter().chain(request_locales.clone()).collect::<Vec<_>>();
+                    let email = TemplateEmail {
+                        template_name: template_name.clone(),
+                        send_mail: SimpleMail {
+                            to: to.clone(),
+                            subject: subject.clone(),
+                            text: String::default(),
+                        },
+                        extra_to: vec![],
+                        context: merge_context(context.clone(), recipient_context),
+                        locales: locale_fallbacks(&requested_locales, &default_locale),
+                        options: MailOptions {
+                            send_at,
+                            ..MailOptions::default()
+                        },
+                    };
+                    let result = validate_recipients(&[to.clone()], &email.options)
+                        .map_err(|errors| -> FailureError {
+                            format_err!("Invalid recipient: {}", validation_details(&errors))
+                                .context(Error::Parse)
+                                .into()
+                        })
+                        .and_then(|_| {
+                            conn.transaction::<_, FailureError, _>(|| enqueue_template(&*conn, &repo_factory, &compiled, &sender, email))
+                        })
+                        .map(|messages| messages.into_iter().next());
+                    batch_recipient_result(to, result)
+                })
+                .collect::<Vec<_>>();
+            Ok(results)
+        })
     }
 }
 
-impl<T, M, F> SimpleMailService for Service<T, M, F>
+impl<T, M, F> Service<T, M, F>
 where
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
     M: ManageConnection<Connection = T>,
     F: ReposFactory<T>,
 {
-    fn send_mail(self, mail: SimpleMail) -> ServiceFuture<()> {
+    /// Renders the template with `data` into the body of `send_mail` and queues it. Template is
+    /// looked up in `locale`, then in the request locales and the default one.
+    /// `extra_to` are the recipients along with `send_mail.to`.
+    fn enqueue_template_email<S>(
+        self,
+        template_name: String,
+        send_mail: SimpleMail,
+        extra_to: Vec<String>,
+        data: S,
+        locale: Option<String>,
+        options: MailOptions,
+    ) -> ServiceFuture<Vec<QueuedEmail>>
+    where
+        S: Serialize,
+    {
+        let to = Some(send_mail.to.clone()).into_iter().chain(extra_to.clone()).collect::<Vec<_>>();
+        let valid = validate_recipients(&to, &options)
+            .and_then(|_| validate_attachments(&options.attachments, &self.static_context.config.attachments))
+            .and_then(|_| validate_send_at(options.send_at, &self.static_context.config.scheduler));
+        if let Err(errors) = valid {
+            return Box::new(future::err(
+                format_err!(
+                    "Invalid recipients, attachments or send time of {} email: {}",
+                    template_name,
+                    validation_details(&errors)
+                )
+                .context(Error::Parse)
+                .into(),
+            ));
+        }
+        let context = match serde_json::to_value(&data) {
+            Ok(context) => context,
+            Err(e) => return Box::new(future::err(e.into())),
+        };
+        let requested_locales = locale.into_iter().chain(self.dynamic_context.locales.clone()).collect::<Vec<_>>();
+        let email = TemplateEmail {
+            template_name,
+            send_mail,
+            extra_to,
+            context,
+            locales: locale_fallbacks(&requested_locales, &self.static_context.config.templates.default_locale),
+            options,
+        };
+        let sender = self.template_sender();
+        let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
+
+        Box::new(self.spawn_on_pool(move |conn| {
+            let compiled = template_registry.get_or_build(
+                &*repo_factory.create_templates_repo_with_sys_acl(&*conn),
+                &*repo_factory.create_template_partials_repo_with_sys_acl(&*conn),
+            )?;
+            conn.transaction::<_, FailureError, _>(|| enqueue_template(&*conn, &repo_factory, &compiled, &sender, email))
+                .map(|messages| messages.iter().map(QueuedEmail::from).collect())
+        }))
+    }
+
+    fn template_sender(&self) -> TemplateSender {
         let SendGridConf { from_email, from_name, .. } = self.static_context.config.sendgrid.clone();
-        let sendgrid_service = self.static_context.sendgrid_service.clone();
+        TemplateSender {
+            from_email,
+            from_name,
+            user_id: self.dynamic_context.user_id,
+            idempotency_key: self.dynamic_context.request_idempotency_key(),
+            idempotency_window: Duration::from_secs(self.static_context.config.idempotency.window_secs),
+            unsubscribe: self.static_context.config.unsubscribe.clone(),
+            moderation_bcc: self.static_context.config.mail.moderation_bcc.clone(),
+        }
+    }
+}
 
-        let payload = SendGridPayload::from_send_mail(mail, from_email.clone(), from_name.clone(), TEXT_PLAIN);
+/// Settings of the template emails which are the same for all recipients of the request
+struct TemplateSender {
+    from_email: String,
+    from_name: String,
+    user_id: Option<UserId>,
+    idempotency_key: Option<String>,
+    idempotency_window: Duration,
+    unsubscribe: UnsubscribeConf,
+    moderation_bcc: Vec<String>,
+}
 
-        let to = payload.get_address_list().join(", ");
-        debug!("Sending email - to {}, subject: {}", to, payload.subject);
-        info!("Sending email - to: {}", to);
+/// Email to render from the template, `locales` are looked up in order
+struct TemplateEmail {
+    template_name: String,
+    send_mail: SimpleMail,
+    extra_to: Vec<String>,
+    context: Value,
+    locales: Vec<String>,
+    options: MailOptions,
+}
+
+/// Queues the email, recipients who disabled emails of its category are skipped, security ones
+/// are always sent. Emails of other categories are split to one email per recipient, copies included,
+/// so that each of them gets their own `unsubscribe_url` in the context and `List-Unsubscribe` header.
+fn enqueue_template<T, F>(
+    conn: &T,
+    repo_factory: &F,
+    compiled: &CompiledTemplates,
+    sender: &TemplateSender,
+    email: TemplateEmail,
+) -> Result<Vec<OutboxMessage>, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    F: ReposFactory<T>,
+{
+    let category = NotificationCategory::of_template(&email.template_name);
+    if !category.is_optional() {
+        return enqueue_rendered(conn, repo_factory, compiled, sender, email, None).map(|message| vec![message]);
+    }
+
+    let preferences_repo = repo_factory.create_notification_preferences_repo_with_sys_acl(conn);
+    let enabled = |addresses: Vec<String>| -> Result<Vec<String>, FailureError> {
+        let mut enabled = vec![];
+        for address in addresses {
+            let preference = preferences_repo.find(&normalize_email(&address), category)?;
+            if preference.map(|preference| !preference.enabled).unwrap_or(false) {
+                info!(
+                    "Skipping recipient - template: {}, to: {}, category {} is disabled by the recipient",
+                    email.template_name, address, category
+                );
+            } else {
+                enabled.push(address);
+            }
+        }
+        Ok(enabled)
+    };
+    let recipients = enabled(
+        Some(email.send_mail.to.clone())
+            .into_iter()
+            .chain(email.extra_to.clone())
+            .chain(email.options.cc.clone())
+            .chain(email.options.bcc.clone())
+            .collect(),
+    )?;
 
+    let mut messages = vec![];
+    for (email, url) in split_by_recipient(&email, recipients, &sender.unsubscribe, category) {
+        messages.push(enqueue_rendered(conn, repo_factory, compiled, sender, email, Some(url))?);
+    }
+    Ok(messages)
+}
+
+/// Copies of the email addressed to each of the recipients alone, along with the unsubscribe link of the recipient.
+/// Repeated addresses get a single copy.
+fn split_by_recipient(
+    email: &TemplateEmail,
+    recipients: Vec<String>,
+    conf: &UnsubscribeConf,
+    category: NotificationCategory,
+) -> Vec<(TemplateEmail, String)> {
+    let mut seen = HashSet::new();
+    recipients
+        .into_iter()
+        .filter(|address| seen.insert(normalize_email(address)))
+        .map(|to| {
+            let url = unsubscribe_url(conf, &to, category);
+            let copy = TemplateEmail {
+                template_name: email.template_name.clone(),
+                send_mail: SimpleMail {
+                    to,
+                    subject: email.send_mail.subject.clone(),
+                    text: email.send_mail.text.clone(),
+                },
+                extra_to: vec![],
+                context: email.context.clone(),
+                locales: email.locales.clone(),
+                options: MailOptions {
+                    cc: vec![],
+                    bcc: vec![],
+                    ..email.options.clone()
+                },
+            };
+            (copy, url)
+        })
+        .collect()
+}
+
+/// Renders the template and queues the email, `unsubscribe_url` is added to the context and headers
+fn enqueue_rendered<T, F>(
+    conn: &T,
+    repo_factory: &F,
+    compiled: &CompiledTemplates,
+    sender: &TemplateSender,
+    email: TemplateEmail,
+    unsubscribe_url: Option<String>,
+) -> Result<OutboxMessage, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    F: ReposFactory<T>,
+{
+    let TemplateEmail {
+        template_name,
+        mut send_mail,
+        extra_to,
+        mut context,
+        locales,
+        mut options,
+    } = email;
+
+    if NotificationCategory::of_template(&template_name) == NotificationCategory::Moderation {
+        options.bcc.extend(sender.moderation_bcc.iter().cloned());
+    }
+
+    if let Some(ref url) = unsubscribe_url {
+        if let Value::Object(ref mut fields) = context {
+            fields.entry("unsubscribe_url").or_insert_with(|| Value::String(url.clone()));
+        }
+    }
+
+    let template = repo_factory
+        .create_templates_repo(conn, sender.user_id)
+        .find_localized(&template_name, &locales)?
+        .ok_or_else(|| format_err!("Template {} not found in locales {:?}", template_name, locales).context(Error::NotFound))?;
+    let rendered = compiled.render(&template, &context)?;
+    send_mail.text = rendered.html;
+    if let Some(subject) = rendered.subject {
+        send_mail.subject = subject;
+    }
+    let send_at = options.send_at.map(SystemTime::from);
+    let mut payload = SendGridPayload::from_send_mail(send_mail, sender.from_email.clone(), sender.from_name.clone(), TEXT_HTML)
+        .with_plain_text(rendered.plain_text)
+        .with_options(extra_to, options);
+    if let Some(url) = unsubscribe_url {
+        payload = payload.with_unsubscribe_url(url);
+    }
+
+    let to = payload.get_address_list().join(", ");
+    debug!("Queueing email - to: {}, subject: {}", to, payload.subject);
+    info!("Queueing email - template: {}, to: {}", template_name, to);
+    let email = OutgoingEmail {
+        scope: idempotency_scope(&template_name, &payload),
+        payload,
+        template: Some(template_name),
+        user_id: sender.user_id,
+        idempotency_key: sender.idempotency_key.clone(),
+        send_at,
+    };
+    enqueue_once(
+        &*repo_factory.create_outbox_repo_with_sys_acl(conn),
+        &*repo_factory.create_notifications_repo_with_sys_acl(conn),
+        &*repo_factory.create_suppressions_repo_with_sys_acl(conn),
+        email,
+        sender.idempotency_window,
+    )
+}
+
+/// Fields of the recipient context replace the shared ones
+fn merge_context(shared: Value, recipient: Value) -> Value {
+    match (shared, recipient) {
+        (Value::Object(mut shared), Value::Object(recipient)) => {
+            shared.extend(recipient);
+            Value::Object(shared)
+        }
+        (shared, Value::Null) => shared,
+        (_, recipient) => recipient,
+    }
+}
+
+fn batch_recipient_result(to: String, result: Result<Option<OutboxMessage>, FailureError>) -> BatchRecipientResult {
+    match result {
+        Ok(Some(message)) => BatchRecipientResult {
+            to,
+            status: match message.status {
+                OutboxStatus::Scheduled => BatchRecipientStatus::Scheduled,
+                _ => BatchRecipientStatus::Queued,
+            },
+            message_id: Some(message.id),
+            error: None,
+        },
+        Ok(None) => BatchRecipientResult {
+            to,
+            status: BatchRecipientStatus::Skipped,
+            message_id: None,
+            error: None,
+        },
+        Err(e) => {
+            warn!("Batch email to {} failed: {}", to, e);
+            BatchRecipientResult {
+                to,
+                status: BatchRecipientStatus::Failed,
+                message_id: None,
+                error: Some(e.iter_chain().map(|cause| cause.to_string()).collect::<Vec<_>>().join(": ")),
+            }
+        }
+    }
+}
+
+impl<T, M, F> SimpleMailService for Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    fn send_mail(self, mail: SimpleMailPayload) -> ServiceFuture<QueuedEmail> {
+        let (mail, extra_to, options) = mail.into_send_mail();
+        let SendGridConf { from_email, from_name, .. } = self.static_context.config.sendgrid.clone();
+        let to = Some(mail.to.clone()).into_iter().chain(extra_to.clone()).collect::<Vec<_>>();
+        let valid = validate_recipients(&to, &options)
+            .and_then(|_| validate_attachments(&options.attachments, &self.static_context.config.attachments))
+            .and_then(|_| validate_send_at(options.send_at, &self.static_context.config.scheduler));
+        let send_at = options.send_at.map(SystemTime::from);
+        let payload =
+            SendGridPayload::from_send_mail(mail, from_email.clone(), from_name.clone(), TEXT_PLAIN).with_options(extra_to, options);
+        let to = payload.get_address_list().join(", ");
+        debug!("Queueing email - to {}, subject: {}", to, payload.subject);
+        info!("Queueing email - to: {}", to);
+        let scope = idempotency_scope(SIMPLE_MAIL_SCOPE, &payload);
         Box::new(
-            sendgrid_service
-                .send(payload)
-                .map_err(|e| e.context("SendgridService failed").into()),
+            valid
+                .map_err(|errors| -> FailureError {
+                    format_err!("Invalid recipients, attachments or send time: {}", validation_details(&errors))
+                        .context(Error::Parse)
+                        .into()
+                })
+                .into_future()
+                .and_then(move |_| self.enqueue_email(payload, None, scope, send_at))
+                .map(|message| QueuedEmail::from(&message))
+                .map_err(|e: FailureError| e.context("Mail service, send_mail endpoint error occured.").into()),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_merge_context() {
+        let shared = value(r#"{"marketplace": "Storiqa", "name": "customer"}"#);
+        assert_eq!(
+            merge_context(shared.clone(), value(r#"{"name": "Alice", "bonus": 10}"#)),
+            value(r#"{"marketplace": "Storiqa", "name": "Alice", "bonus": 10}"#)
+        );
+        assert_eq!(merge_context(shared.clone(), Value::Null), shared);
+        assert_eq!(
+            merge_context(Value::Null, value(r#"{"name": "Alice"}"#)),
+            value(r#"{"name": "Alice"}"#)
+        );
+    }
+
+    #[test]
+    fn test_copies_get_their_own_unsubscribe_link() {
+        let conf = UnsubscribeConf {
+            url: "https://storiqa.com/api/notifications".to_string(),
+            secret: "secret".to_string(),
+        };
+        let email = TemplateEmail {
+            template_name: "announcement".to_string(),
+            send_mail: SimpleMail {
+                to: "to@storiqa.com".to_string(),
+                subject: "subject".to_string(),
+                text: "text".to_string(),
+            },
+            extra_to: vec![],
+            context: Value::Null,
+            locales: vec!["en".to_string()],
+            options: MailOptions {
+                cc: vec!["cc@storiqa.com".to_string()],
+                bcc: vec!["bcc@storiqa.com".to_string(), "TO@storiqa.com".to_string()],
+                ..MailOptions::default()
+            },
+        };
+        let recipients = vec![
+            "to@storiqa.com".to_string(),
+            "cc@storiqa.com".to_string(),
+            "bcc@storiqa.com".to_string(),
+            "TO@storiqa.com".to_string(),
+        ];
+
+        let copies = split_by_recipient(&email, recipients, &conf, NotificationCategory::Marketing);
+        let addresses = copies.iter().map(|&(ref copy, _)| copy.send_mail.to.clone()).collect::<Vec<_>>();
+        assert_eq!(addresses, vec!["to@storiqa.com", "cc@storiqa.com", "bcc@storiqa.com"]);
+        for (copy, url) in copies {
+            let to = copy.send_mail.to.clone();
+            let payload = SendGridPayload::from_send_mail(copy.send_mail, "from@storiqa.com".to_string(), "Storiqa".to_string(), TEXT_HTML)
+                .with_options(copy.extra_to, copy.options)
+                .with_unsubscribe_url(url);
+            assert_eq!(payload.get_address_list(), vec![to.clone()]);
+            assert_eq!(
+                payload.headers.get("List-Unsubscribe"),
+                Some(&format!("<{}>", unsubscribe_url(&conf, &to, NotificationCategory::Marketing)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_failed_recipient_result() {
+        let e: FailureError = format_err!("Template announcement not found").context(Error::NotFound).into();
+        let result = batch_recipient_result("user@storiqa.com".to_string(), Err(e));
+        assert_eq!(result.status, BatchRecipientStatus::Failed);
+        assert_eq!(result.message_id, None);
+        assert_eq!(result.error, Some("Not found: Template announcement not found".to_string()));
+
+        let skipped = batch_recipient_result("user@storiqa.com".to_string(), Ok(None));
+        assert_eq!(skipped.status, BatchRecipientStatus::Skipped);
+        assert_eq!(skipped.error, None);
+    }
+}
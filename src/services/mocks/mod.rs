@@ -1,2 +1,3 @@
 pub mod emarsys;
 pub mod sendgrid;
+pub mod users;
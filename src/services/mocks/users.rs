@@ -0,0 +1,19 @@
+use futures::future;
+
+use stq_types::UserId;
+
+use models::UserAccount;
+use services::types::ServiceFuture;
+use services::users::UsersClient;
+
+/// Every user has an account with the address `user<id>@storiqa.com`
+pub struct UsersClientMock;
+
+impl UsersClient for UsersClientMock {
+    fn get_account(&self, user_id: UserId) -> ServiceFuture<Option<UserAccount>> {
+        Box::new(future::ok(Some(UserAccount {
+            id: user_id,
+            email: format!("user{}@storiqa.com", user_id),
+        })))
+    }
+}
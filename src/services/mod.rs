@@ -1,9 +1,26 @@
+pub mod attachments;
 pub mod emarsys;
 pub mod mail;
 pub mod mocks;
+pub mod notification_preferences;
+pub mod notifications;
+pub mod outbox;
+pub mod payload_fields;
+pub mod plain_text;
+pub mod recipients;
+pub mod scheduler;
 pub mod sendgrid;
+pub mod sendgrid_events;
+pub mod smtp;
+pub mod suppressions;
+pub mod template_helpers;
+pub mod template_registry;
+pub mod template_validation;
 pub mod templates;
+pub mod transport_chain;
 pub mod types;
+pub mod unsubscribe;
 pub mod user_roles;
+pub mod users;
 
 pub use self::types::Service;
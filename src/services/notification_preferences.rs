@@ -0,0 +1,130 @@
+//! Notification preferences Services, users enable and disable categories of emails sent to their addresses
+
+use std::borrow::Cow;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use r2d2::ManageConnection;
+use validator::{ValidationError, ValidationErrors};
+
+use errors::{validation_details, Error};
+use models::{normalize_email, NewNotificationPreference, NotificationPreference, UpdateNotificationPreference};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait NotificationPreferencesService {
+    /// List preferences of the current user
+    fn list_notification_preferences(self) -> ServiceFuture<Vec<NotificationPreference>>;
+    /// Enable or disable category of emails for the address of the current user
+    fn update_notification_preference(self, payload: UpdateNotificationPreference) -> ServiceFuture<NotificationPreference>;
+    /// Delete preference, emails of its category are sent again
+    fn delete_notification_preference(self, id: i32) -> ServiceFuture<NotificationPreference>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > NotificationPreferencesService for Service<T, M, F>
+{
+    fn list_notification_preferences(self) -> ServiceFuture<Vec<NotificationPreference>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let preferences_repo = repo_factory.create_notification_preferences_repo(&*conn, current_uid);
+            current_uid
+                .ok_or_else(|| format_err!("Preferences of unauthorized user").context(Error::Forbidden).into())
+                .and_then(|user_id| preferences_repo.list_for_user(user_id))
+                .map_err(|e: FailureError| {
+                    e.context("Service notification preferences, list_notification_preferences endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+
+    fn update_notification_preference(self, payload: UpdateNotificationPreference) -> ServiceFuture<NotificationPreference> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let users_client = self.static_context.users_client.clone();
+
+        let user_id = match current_uid {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    format_err!("Preferences of unauthorized user")
+                        .context(Error::Forbidden)
+                        .context("Service notification preferences, update_notification_preference endpoint error occurred.")
+                        .into(),
+                ))
+            }
+        };
+
+        if !payload.category.is_optional() && !payload.enabled {
+            let mut errors = ValidationErrors::new();
+            let mut error = ValidationError::new("not_optional");
+            error.message = Some(Cow::from(format!("Emails of category {} can't be disabled", payload.category)));
+            errors.add("category", error);
+            return Box::new(future::err(
+                format_err!("Invalid notification preference: {}", validation_details(&errors))
+                    .context(Error::Parse)
+                    .context("Service notification preferences, update_notification_preference endpoint error occurred.")
+                    .into(),
+            ));
+        }
+
+        // the address is taken from the account, users can't manage addresses they don't own
+        Box::new(
+            users_client
+                .get_account(user_id)
+                .and_then(move |account| {
+                    account.ok_or_else(|| format_err!("Account of user {} not found", user_id).context(Error::NotFound).into())
+                })
+                .and_then(move |account| {
+                    self.spawn_on_pool(move |conn| {
+                        let preferences_repo = repo_factory.create_notification_preferences_repo(&*conn, current_uid);
+                        conn.transaction::<NotificationPreference, FailureError, _>(move || {
+                            preferences_repo.upsert(NewNotificationPreference {
+                                user_id: Some(user_id),
+                                email: normalize_email(&account.email),
+                                category: payload.category,
+                                enabled: payload.enabled,
+                            })
+                        })
+                    })
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service notification preferences, update_notification_preference endpoint error occurred.")
+                        .into()
+                }),
+        )
+    }
+
+    fn delete_notification_preference(self, id: i32) -> ServiceFuture<NotificationPreference> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let preferences_repo = repo_factory.create_notification_preferences_repo(&*conn, current_uid);
+            preferences_repo
+                .delete(id)
+                .and_then(|preference| {
+                    preference.ok_or_else(|| {
+                        format_err!("Notification preference {} not found", id)
+                            .context(Error::NotFound)
+                            .into()
+                    })
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service notification preferences, delete_notification_preference endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+}
@@ -0,0 +1,77 @@
+//! Notifications Services, presents read access to the delivery log
+
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use errors::Error;
+use models::{EngagementSearch, EngagementStats, Notification, NotificationsSearch};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait NotificationsService {
+    /// Search delivery log
+    fn search_notifications(self, search: NotificationsSearch, offset: i64, count: i64) -> ServiceFuture<Vec<Notification>>;
+    /// Get delivery log entry by id
+    fn get_notification(self, id: Uuid) -> ServiceFuture<Notification>;
+    /// Engagement with the emails of each template per day, the last 30 days if the start is not set
+    fn engagement_report(self, search: EngagementSearch) -> ServiceFuture<Vec<EngagementStats>>;
+}
+
+/// Period of the engagement report in days when the start is not set
+const DEFAULT_REPORT_DAYS: u64 = 30;
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > NotificationsService for Service<T, M, F>
+{
+    fn search_notifications(self, search: NotificationsSearch, offset: i64, count: i64) -> ServiceFuture<Vec<Notification>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let notifications_repo = repo_factory.create_notifications_repo(&*conn, current_uid);
+            notifications_repo
+                .search(search, offset, count)
+                .map_err(|e: FailureError| e.context("Service notifications, search_notifications endpoint error occurred.").into())
+        })
+    }
+
+    fn get_notification(self, id: Uuid) -> ServiceFuture<Notification> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let notifications_repo = repo_factory.create_notifications_repo(&*conn, current_uid);
+            notifications_repo
+                .get(id)
+                .and_then(|notification| notification.ok_or_else(|| format_err!("Notification {} not found", id).context(Error::NotFound).into()))
+                .map_err(|e: FailureError| e.context("Service notifications, get_notification endpoint error occurred.").into())
+        })
+    }
+
+    fn engagement_report(self, search: EngagementSearch) -> ServiceFuture<Vec<EngagementStats>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let search = EngagementSearch {
+            sent_from: search.sent_from.or_else(|| Some(SystemTime::now() - Duration::from_secs(DEFAULT_REPORT_DAYS * 24 * 60 * 60))),
+            ..search
+        };
+
+        self.spawn_on_pool(move |conn| {
+            let notifications_repo = repo_factory.create_notifications_repo(&*conn, current_uid);
+            notifications_repo
+                .engagement_report(search)
+                .map_err(|e: FailureError| e.context("Service notifications, engagement_report endpoint error occurred.").into())
+        })
+    }
+}
@@ -0,0 +1,521 @@
+//! Outbox service stores rendered emails and delivers them in background
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::{Context, Fail};
+use futures::future;
+use futures::prelude::*;
+use r2d2::ManageConnection;
+use rand::{self, Rng};
+use sha1::{Digest, Sha1};
+use uuid::Uuid;
+
+use config::{OutboxConf, RetryConf};
+use errors::{Error, SuppressedRecipient};
+use models::{
+    normalize_email, NewIdempotencyKey, NewNotification, NewOutboxAttempt, NewOutboxMessage, NotificationStatus, OutboxMessage,
+    OutboxMessageDetails, OutboxStatus, OutgoingEmail, SendGridPayload,
+};
+use repos::{NotificationsRepo, OutboxRepo, ReposFactory, SuppressionsRepo};
+use services::transport_chain::failed_provider;
+use services::types::{Service, ServiceFuture};
+
+pub trait OutboxService {
+    /// Store email in the outbox, it will be delivered by the outbox worker, or by `send_at` if it's set.
+    /// Requests with the same idempotency key are deduplicated within `scope`.
+    fn enqueue_email(
+        self,
+        payload: SendGridPayload,
+        template: Option<String>,
+        scope: String,
+        send_at: Option<SystemTime>,
+    ) -> ServiceFuture<OutboxMessage>;
+    /// Deliver emails which are due
+    fn deliver_pending_emails(self) -> ServiceFuture<()>;
+    /// List messages which won't be retried anymore
+    fn list_dead_letters(self, offset: i64, count: i64) -> ServiceFuture<Vec<OutboxMessage>>;
+    /// Get outbox message with its delivery attempts
+    fn get_outbox_message(self, message_id: Uuid) -> ServiceFuture<OutboxMessageDetails>;
+    /// Put dead letter back to the queue
+    fn requeue_dead_letter(self, message_id: Uuid) -> ServiceFuture<OutboxMessage>;
+}
+
+impl<T, M, F> OutboxService for Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    fn enqueue_email(
+        self,
+        payload: SendGridPayload,
+        template: Option<String>,
+        scope: String,
+        send_at: Option<SystemTime>,
+    ) -> ServiceFuture<OutboxMessage> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let email = OutgoingEmail {
+            payload,
+            template,
+            user_id: self.dynamic_context.user_id,
+            idempotency_key: self.dynamic_context.request_idempotency_key(),
+            scope,
+            send_at,
+        };
+        let idempotency_window = Duration::from_secs(self.static_context.config.idempotency.window_secs);
+        self.spawn_on_pool(move |conn| {
+            let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
+            let notifications_repo = repo_factory.create_notifications_repo_with_sys_acl(&*conn);
+            let suppressions_repo = repo_factory.create_suppressions_repo_with_sys_acl(&*conn);
+            conn.transaction::<OutboxMessage, FailureError, _>(move || {
+                enqueue_once(&*outbox_repo, &*notifications_repo, &*suppressions_repo, email, idempotency_window)
+            })
+            .map_err(|e: FailureError| e.context("Service OutboxService, enqueue_email endpoint error occurred.").into())
+        })
+    }
+
+    fn deliver_pending_emails(self) -> ServiceFuture<()> {
+        let OutboxConf { batch_size, lease_ms, .. } = self.static_context.config.outbox.clone();
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service = self.clone();
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
+                outbox_repo.claim_due(batch_size, Duration::from_millis(lease_ms))
+            })
+            .and_then(move |messages| {
+                future::join_all(messages.into_iter().map(move |message| {
+                    let message_id = message.id;
+                    service.clone().deliver_email(message).then(move |res| {
+                        if let Err(e) = res {
+                            error!("Outbox message {} could not be processed: {:?}", message_id, e);
+                        }
+                        Ok(())
+                    })
+                }))
+            })
+            .map(|_| ())
+            .map_err(|e: FailureError| {
+                e.context("Service OutboxService, deliver_pending_emails endpoint error occurred.")
+                    .into()
+            }),
+        )
+    }
+
+    fn list_dead_letters(self, offset: i64, count: i64) -> ServiceFuture<Vec<OutboxMessage>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        self.spawn_on_pool(move |conn| {
+            let outbox_repo = repo_factory.create_outbox_repo(&*conn, user_id);
+            outbox_repo
+                .list_by_status(OutboxStatus::DeadLetter, offset, count)
+                .map_err(|e: FailureError| {
+                    e.context("Service OutboxService, list_dead_letters endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+
+    fn get_outbox_message(self, message_id: Uuid) -> ServiceFuture<OutboxMessageDetails> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        self.spawn_on_pool(move |conn| {
+            let outbox_repo = repo_factory.create_outbox_repo(&*conn, user_id);
+            outbox_repo
+                .get(message_id)
+                .and_then(|message| {
+                    message.ok_or_else(|| {
+                        format_err!("Outbox message {} not found", message_id)
+                            .context(Error::NotFound)
+                            .into()
+                    })
+                })
+                .and_then(|message| {
+                    outbox_repo
+                        .list_attempts(message_id)
+                        .map(|attempts| OutboxMessageDetails { message, attempts })
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service OutboxService, get_outbox_message endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+
+    fn requeue_dead_letter(self, message_id: Uuid) -> ServiceFuture<OutboxMessage> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        self.spawn_on_pool(move |conn| {
+            let outbox_repo = repo_factory.create_outbox_repo(&*conn, user_id);
+            let notifications_repo = repo_factory.create_notifications_repo(&*conn, user_id);
+            conn.transaction::<OutboxMessage, FailureError, _>(move || {
+                let message = outbox_repo
+                    .requeue(message_id)?
+                    .ok_or_else(|| format_err!("Dead letter {} not found", message_id).context(Error::NotFound))?;
+                notifications_repo.update_status(message_id, NotificationStatus::Queued)?;
+                info!("Outbox message {} requeued", message.id);
+                Ok(message)
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service OutboxService, requeue_dead_letter endpoint error occurred.")
+                    .into()
+            })
+        })
+    }
+}
+
+impl<T, M, F> Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    /// Makes a single delivery attempt and records its outcome
+    fn deliver_email(self, message: OutboxMessage) -> ServiceFuture<()> {
+        let mail_transports = self.static_context.mail_transports.clone();
+        let config = self.static_context.config.clone();
+        let repo_factory = self.static_context.repo_factory.clone();
+        let message_id = message.id;
+        let attempts = message.attempts + 1;
+        Box::new(
+            message
+                .sendgrid_payload()
+                .map(|payload| payload.with_notification_id(message_id))
+                .into_future()
+                .and_then(move |payload| {
+                    let to = payload.get_address_list().join(", ");
+                    debug!(
+                        "Delivering outbox message {} - to: {}, subject: {}",
+                        message_id, to, payload.subject
+                    );
+                    mail_transports
+                        .send(payload)
+                        .map_err(|e| e.context("Email transports failed").into())
+                })
+                .then(move |res| {
+                    let (attempt, status, next_attempt_at) = match res {
+                        Ok(provider) => {
+                            info!("Outbox message {} delivered by {}", message_id, provider);
+                            let attempt = NewOutboxAttempt {
+                                message_id,
+                                succeeded: true,
+                                error: None,
+                                provider: Some(provider.to_string()),
+                            };
+                            (attempt, OutboxStatus::Sent, SystemTime::now())
+                        }
+                        Err(e) => {
+                            // retries follow the policy of the provider which failed last
+                            let provider = failed_provider(&e);
+                            let retry = config.mail_retry_policy(provider.unwrap_or_else(|| config.mail_provider()));
+                            let attempt = NewOutboxAttempt {
+                                message_id,
+                                succeeded: false,
+                                error: Some(e.find_root_cause().to_string()),
+                                provider: provider.map(|provider| provider.to_string()),
+                            };
+                            if is_permanent_failure(&e) || attempts >= retry.max_attempts {
+                                error!(
+                                    "Outbox message {} moved to dead letters after {} attempts: {:?}",
+                                    message_id, attempts, e
+                                );
+                                (attempt, OutboxStatus::DeadLetter, SystemTime::now())
+                            } else {
+                                let delay = retry_delay(&retry, attempts);
+                                warn!("Outbox message {} delivery failed, retrying in {:?}: {:?}", message_id, delay, e);
+                                (attempt, OutboxStatus::Pending, SystemTime::now() + delay)
+                            }
+                        }
+                    };
+                    self.spawn_on_pool(move |conn| {
+                        let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
+                        let notifications_repo = repo_factory.create_notifications_repo_with_sys_acl(&*conn);
+                        conn.transaction::<(), FailureError, _>(move || {
+                            let provider = attempt.provider.clone();
+                            outbox_repo.record_attempt(attempt, status, next_attempt_at)?;
+                            match (status, provider) {
+                                (OutboxStatus::Sent, Some(provider)) => notifications_repo.mark_sent(message_id, &provider).map(|_| ()),
+                                (OutboxStatus::DeadLetter, _) => {
+                                    notifications_repo.update_status(message_id, NotificationStatus::Failed).map(|_| ())
+                                }
+                                _ => Ok(()),
+                            }
+                        })
+                    })
+                })
+                .map(|_| ()),
+        )
+    }
+}
+
+/// Stores the email in the outbox and the delivery log. If the idempotency key has
+/// already been used in the email scope within `window`, the message queued back then
+/// is returned instead. Emails with `send_at` in the future are stored as scheduled.
+pub fn enqueue_once(
+    outbox_repo: &OutboxRepo,
+    notifications_repo: &NotificationsRepo,
+    suppressions_repo: &SuppressionsRepo,
+    email: OutgoingEmail,
+    window: Duration,
+) -> Result<OutboxMessage, FailureError> {
+    let OutgoingEmail {
+        payload,
+        template,
+        user_id,
+        idempotency_key,
+        scope,
+        send_at,
+    } = email;
+
+    if let Some(ref key) = idempotency_key {
+        if let Some(message) = outbox_repo.find_by_idempotency_key(key, &scope, SystemTime::now() - window)? {
+            info!(
+                "Request with idempotency key {} has already queued outbox message {}",
+                key, message.id
+            );
+            return Ok(message);
+        }
+    }
+
+    let recipients = payload
+        .get_address_list()
+        .iter()
+        .map(|email| normalize_email(email))
+        .collect::<Vec<_>>();
+    if let Some(suppression) = suppressions_repo.find_any(&recipients)?.into_iter().next() {
+        info!(
+            "Refusing to send email to suppressed address {} ({})",
+            suppression.email, suppression.reason
+        );
+        return Err(format_err!("Recipient {} is suppressed", suppression.email)
+            .context(Error::Suppressed(SuppressedRecipient {
+                email: suppression.email,
+                reason: suppression.reason.to_string(),
+            }))
+            .into());
+    }
+
+    let mut new_message = NewOutboxMessage::from_payload(&payload)?;
+    if let Some(send_at) = send_at.filter(|send_at| *send_at > SystemTime::now()) {
+        new_message = new_message.scheduled_at(send_at);
+    }
+    let message = outbox_repo.create(new_message)?;
+    notifications_repo.create(NewNotification {
+        id: message.id,
+        recipient: payload.get_address_list().join(", "),
+        template,
+        subject: payload.subject.clone(),
+        user_id,
+    })?;
+    if let Some(key) = idempotency_key {
+        outbox_repo.save_idempotency_key(NewIdempotencyKey {
+            key,
+            scope,
+            message_id: message.id,
+        })?;
+    }
+    Ok(message)
+}
+
+/// Idempotency scope of the email, so that one upstream request can still
+/// send different emails or the same email to different recipients.
+pub fn idempotency_scope(kind: &str, payload: &SendGridPayload) -> String {
+    let recipients = payload.get_address_list().join(",");
+    let digest = Sha1::digest(format!("{}\n{}", recipients, payload.subject).as_bytes());
+    format!("{}:{:x}", kind, digest)
+}
+
+/// Checks if the provider refused the message itself, so neither retrying
+/// nor falling over to another provider will help
+pub fn is_permanent_failure(e: &FailureError) -> bool {
+    e.iter_chain().any(
+        |cause| match cause.downcast_ref::<Context<Error>>().map(|context| context.get_context()) {
+            Some(Error::ProviderRejected) | Some(Error::Parse) => true,
+            _ => false,
+        },
+    )
+}
+
+/// Exponential backoff with random jitter for the `attempts`-th failed attempt
+fn retry_delay(retry: &RetryConf, attempts: i32) -> Duration {
+    let exponent = (attempts - 1).max(0);
+    let backoff = (retry.initial_backoff_ms as f64 * retry.backoff_multiplier.powi(exponent)).min(retry.max_backoff_ms as f64);
+    let jitter = if retry.jitter > 0.0 {
+        rand::thread_rng().gen_range(-retry.jitter, retry.jitter)
+    } else {
+        0.0
+    };
+    Duration::from_millis((backoff * (1.0 + jitter)).max(0.0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use mime::TEXT_PLAIN;
+
+    use stq_static_resources::SimpleMail;
+
+    use super::*;
+    use models::{NewSuppression, Suppression, SuppressionReason};
+    use repos::repo_factory::tests::{NotificationsRepoMock, OutboxRepoMock};
+    use repos::types::RepoResult;
+
+    /// Suppressions repo with a single suppressed address
+    struct SuppressionsRepoStub;
+
+    impl SuppressionsRepo for SuppressionsRepoStub {
+        fn list(&self, _email: Option<&str>, _offset: i64, _count: i64) -> RepoResult<Vec<Suppression>> {
+            Ok(vec![])
+        }
+
+        fn find_any(&self, emails: &[String]) -> RepoResult<Vec<Suppression>> {
+            Ok(emails
+                .iter()
+                .filter(|email| *email == "bounced@storiqa.com")
+                .map(|email| Suppression {
+                    id: 1,
+                    email: email.clone(),
+                    reason: SuppressionReason::HardBounce,
+                    source: "sendgrid".to_string(),
+                    created_at: SystemTime::now(),
+                })
+                .collect())
+        }
+
+        fn upsert(&self, payload: NewSuppression) -> RepoResult<Suppression> {
+            Ok(Suppression {
+                id: 1,
+                email: payload.email,
+                reason: payload.reason,
+                source: payload.source,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn delete(&self, _email: &str) -> RepoResult<Option<Suppression>> {
+            Ok(None)
+        }
+    }
+
+    fn outgoing_email(to: &str) -> OutgoingEmail {
+        OutgoingEmail {
+            payload: payload(to, "Hello"),
+            template: None,
+            user_id: None,
+            idempotency_key: None,
+            scope: "simple_mail".to_string(),
+            send_at: None,
+        }
+    }
+
+    fn payload(to: &str, subject: &str) -> SendGridPayload {
+        let mail = SimpleMail {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            text: "text".to_string(),
+        };
+        SendGridPayload::from_send_mail(mail, "from@storiqa.com".to_string(), "Storiqa".to_string(), TEXT_PLAIN)
+    }
+
+    fn retry_conf(jitter: f64) -> RetryConf {
+        RetryConf {
+            max_attempts: 5,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 10000,
+            backoff_multiplier: 2.0,
+            jitter,
+        }
+    }
+
+    #[test]
+    fn test_retry_delay_grows_exponentially() {
+        let retry = retry_conf(0.0);
+        assert_eq!(retry_delay(&retry, 1), Duration::from_millis(1000));
+        assert_eq!(retry_delay(&retry, 2), Duration::from_millis(2000));
+        assert_eq!(retry_delay(&retry, 3), Duration::from_millis(4000));
+        assert_eq!(retry_delay(&retry, 5), Duration::from_millis(10000));
+    }
+
+    #[test]
+    fn test_retry_delay_jitter_is_bounded() {
+        let retry = retry_conf(0.5);
+        for _ in 0..100 {
+            let delay = retry_delay(&retry, 2);
+            assert!(delay >= Duration::from_millis(1000) && delay <= Duration::from_millis(3000));
+        }
+    }
+
+    #[test]
+    fn test_provider_rejection_is_permanent() {
+        let rejected: FailureError = format_err!("Bad request").context(Error::ProviderRejected).into();
+        let rejected: FailureError = rejected.context("Email transports failed").into();
+        assert!(is_permanent_failure(&rejected));
+
+        let timeout: FailureError = format_err!("Timeout").context(Error::HttpClient).into();
+        assert!(!is_permanent_failure(&timeout));
+    }
+
+    #[test]
+    fn test_suppressed_recipient_is_refused() {
+        let enqueue = |to: &str| {
+            enqueue_once(
+                &OutboxRepoMock,
+                &NotificationsRepoMock,
+                &SuppressionsRepoStub,
+                outgoing_email(to),
+                Duration::from_secs(60),
+            )
+        };
+        assert!(enqueue("user@storiqa.com").is_ok());
+
+        let refused = enqueue("Bounced@storiqa.com").unwrap_err();
+        let suppressed = refused
+            .iter_chain()
+            .filter_map(|cause| cause.downcast_ref::<Context<Error>>())
+            .any(|context| match context.get_context() {
+                Error::Suppressed(ref recipient) => recipient.email == "bounced@storiqa.com" && recipient.reason == "hard_bounce",
+                _ => false,
+            });
+        assert!(suppressed);
+    }
+
+    #[test]
+    fn test_future_email_is_scheduled() {
+        let enqueue = |send_at: Option<SystemTime>| {
+            let email = OutgoingEmail {
+                send_at,
+                ..outgoing_email("user@storiqa.com")
+            };
+            enqueue_once(
+                &OutboxRepoMock,
+                &NotificationsRepoMock,
+                &SuppressionsRepoStub,
+                email,
+                Duration::from_secs(60),
+            )
+            .unwrap()
+        };
+        let tomorrow = SystemTime::now() + Duration::from_secs(24 * 60 * 60);
+        let scheduled = enqueue(Some(tomorrow));
+        assert_eq!(scheduled.status, OutboxStatus::Scheduled);
+        assert_eq!(scheduled.next_attempt_at, tomorrow);
+
+        assert_eq!(enqueue(None).status, OutboxStatus::Pending);
+        let yesterday = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        assert_eq!(enqueue(Some(yesterday)).status, OutboxStatus::Pending);
+    }
+
+    #[test]
+    fn test_idempotency_scope_depends_on_recipients_and_subject() {
+        let scope = idempotency_scope("simple_mail", &payload("a@storiqa.com", "Hello"));
+        assert_eq!(scope, idempotency_scope("simple_mail", &payload("a@storiqa.com", "Hello")));
+        assert_ne!(scope, idempotency_scope("simple_mail", &payload("b@storiqa.com", "Hello")));
+        assert_ne!(scope, idempotency_scope("simple_mail", &payload("a@storiqa.com", "Bye")));
+        assert_ne!(
+            scope,
+            idempotency_scope("order_create_for_user", &payload("a@storiqa.com", "Hello"))
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Field paths of email payload types, read from their `Deserialize` implementation,
+//! so variables available in templates follow the payloads of `stq_static_resources`.
+use std::cell::RefCell;
+use std::iter;
+
+use serde::de::value::{Error as ValueError, MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+
+/// Paths of all fields of the payload, nested struct fields are joined with dots, e.g. `user.email`
+pub fn field_paths<T: DeserializeOwned>() -> Vec<String> {
+    let paths = RefCell::new(vec![]);
+    // Fields are recorded as soon as a struct is entered, so they are known
+    // even if some value can't be made up from the placeholder data
+    let _ = T::deserialize(Tracer {
+        prefix: None,
+        paths: &paths,
+    });
+    paths.into_inner()
+}
+
+/// Deserializer feeding placeholder values to the payload and recording its field paths
+struct Tracer<'a> {
+    prefix: Option<String>,
+    paths: &'a RefCell<Vec<String>>,
+}
+
+impl<'a> Tracer<'a> {
+    fn path(&self, field: &str) -> String {
+        match self.prefix {
+            Some(ref prefix) => format!("{}.{}", prefix, field),
+            None => field.to_string(),
+        }
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for Tracer<'a> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_i8(0)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_i16(0)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_i32(0)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_u8(0)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_u16(0)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_u32(0)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_f32(0.0)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_char(' ')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_string(String::new())
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_byte_buf(vec![])
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_seq(SeqDeserializer::<_, ValueError>::new(iter::empty::<()>()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_map(MapDeserializer::<_, ValueError>::new(iter::empty::<((), ())>()))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        let paths = fields.iter().map(|field| self.path(field)).collect::<Vec<_>>();
+        self.paths.borrow_mut().extend(paths.iter().cloned());
+        visitor.visit_map(StructFields {
+            fields: fields.iter().zip(paths),
+            current: None,
+            paths: self.paths,
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        let variant: &'static str = variants.first().cloned().unwrap_or_default();
+        visitor.visit_enum(variant.into_deserializer())
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_unit()
+    }
+}
+
+struct StructFields<'a, I> {
+    fields: I,
+    current: Option<String>,
+    paths: &'a RefCell<Vec<String>>,
+}
+
+impl<'de, 'a, I> MapAccess<'de> for StructFields<'a, I>
+where
+    I: Iterator<Item = (&'static &'static str, String)>,
+{
+    type Error = ValueError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, ValueError> {
+        match self.fields.next() {
+            Some((field, path)) => {
+                self.current = Some(path);
+                seed.deserialize((*field).into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, ValueError> {
+        seed.deserialize(Tracer {
+            prefix: self.current.take(),
+            paths: self.paths,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    enum State {
+        New,
+        Paid,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Customer {
+        email: String,
+        first_name: Option<String>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Order {
+        customer: Customer,
+        slug: i32,
+        state: State,
+        tags: Vec<String>,
+        cluster_url: String,
+    }
+
+    #[test]
+    fn test_field_paths_of_nested_structs() {
+        assert_eq!(
+            field_paths::<Order>(),
+            vec![
+                "customer",
+                "slug",
+                "state",
+                "tags",
+                "cluster_url",
+                "customer.email",
+                "customer.first_name"
+            ]
+        );
+    }
+}
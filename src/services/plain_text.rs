@@ -0,0 +1,68 @@
+//! Plain text alternative of HTML emails for text-only mail clients
+use regex::{Captures, Regex};
+
+lazy_static! {
+    static ref HIDDEN: Regex = Regex::new(r"(?is)<(head|style|script|title)[^>]*>.*?</(head|style|script|title)\s*>").unwrap();
+    static ref LINKS: Regex = Regex::new(r#"(?is)<a\s[^>]*href\s*=\s*["']([^"']*)["'][^>]*>(.*?)</a\s*>"#).unwrap();
+    static ref LINE_BREAKS: Regex = Regex::new(r"(?i)<br\s*/?>").unwrap();
+    static ref BLOCKS: Regex = Regex::new(r"(?i)</?(p|div|tr|table|h[1-6]|ul|ol|li|blockquote)(\s[^>]*)?>").unwrap();
+    static ref TAGS: Regex = Regex::new(r"(?s)<[^>]*>").unwrap();
+    static ref SPACES: Regex = Regex::new(r"[ \t\r\f]+").unwrap();
+    static ref BLANK_LINES: Regex = Regex::new(r"\n{3,}").unwrap();
+}
+
+/// Converts rendered HTML email to readable plain text. Links are kept as `text (url)`,
+/// block elements and line breaks become new lines.
+pub fn html_to_text(html: &str) -> String {
+    let text = HIDDEN.replace_all(html, "");
+    let text = LINKS.replace_all(&text, |caps: &Captures| {
+        let label = TAGS.replace_all(&caps[2], "").trim().to_string();
+        let url = caps[1].trim();
+        if label.is_empty() || label == url {
+            url.to_string()
+        } else {
+            format!("{} ({})", label, url)
+        }
+    });
+    let text = LINE_BREAKS.replace_all(&text, "\n");
+    let text = BLOCKS.replace_all(&text, "\n");
+    let text = TAGS.replace_all(&text, "");
+    let text = decode_entities(&text);
+    let text = SPACES.replace_all(&text, " ");
+    let text = text.lines().map(|line| line.trim()).collect::<Vec<_>>().join("\n");
+    BLANK_LINES.replace_all(&text, "\n\n").trim().to_string()
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&#x3D;", "=")
+        .replace("&#x60;", "`")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text_drops_markup() {
+        let html = "<html><head><title>Order</title><style>p { color: red; }</style></head>\
+                    <body><h1>Hello,&nbsp;John</h1><p>Your order is <b>paid</b>.<br>Thanks &amp; bye</p></body></html>";
+        assert_eq!(html_to_text(html), "Hello, John\n\nYour order is paid.\nThanks & bye");
+    }
+
+    #[test]
+    fn test_html_to_text_keeps_links() {
+        let html = r#"<p>Please <a href="https://storiqa.com/verify?token=1">verify your email</a></p>
+                      <p><a href="https://storiqa.com">https://storiqa.com</a></p>"#;
+        assert_eq!(
+            html_to_text(html),
+            "Please verify your email (https://storiqa.com/verify?token=1)\n\nhttps://storiqa.com"
+        );
+    }
+}
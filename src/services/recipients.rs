@@ -0,0 +1,106 @@
+//! Checks recipients of the emails before they are queued: addresses of `to`,
+//! `cc`, `bcc`, `reply_to` and the total number of recipients.
+use std::borrow::Cow;
+
+use validator::{validate_email, ValidationError, ValidationErrors};
+
+use models::MailOptions;
+
+/// SendGrid accepts at most 1000 recipients per message
+pub const MAX_RECIPIENTS: usize = 1000;
+
+/// Validates recipients of the email, errors are keyed by the field of the address
+pub fn validate_recipients(to: &[String], options: &MailOptions) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if to.is_empty() {
+        errors.add("to", error("required", "At least one recipient is required".to_string()));
+    }
+
+    let fields: [(&'static str, &[String]); 3] = [("to", to), ("cc", &options.cc), ("bcc", &options.bcc)];
+    for &(field, addresses) in fields.iter() {
+        for address in addresses {
+            if !validate_email(address.as_str()) {
+                errors.add(field, error("email", format!("Invalid email address {:?}", address)));
+            }
+        }
+    }
+    if let Some(ref reply_to) = options.reply_to {
+        if !validate_email(reply_to.as_str()) {
+            errors.add("reply_to", error("email", format!("Invalid email address {:?}", reply_to)));
+        }
+    }
+
+    if to.len() + options.cc.len() + options.bcc.len() > MAX_RECIPIENTS {
+        errors.add("to", error("count", format!("At most {} recipients are allowed", MAX_RECIPIENTS)));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn error(code: &'static str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses(addresses: &[&str]) -> Vec<String> {
+        addresses.iter().map(|address| address.to_string()).collect()
+    }
+
+    fn codes(result: Result<(), ValidationErrors>, field: &str) -> Vec<String> {
+        result
+            .unwrap_err()
+            .inner()
+            .get(field)
+            .map(|errors| errors.iter().map(|error| error.code.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_valid_recipients() {
+        let options = MailOptions {
+            cc: addresses(&["manager@storiqa.com"]),
+            bcc: addresses(&["support@storiqa.com"]),
+            reply_to: Some("noreply@storiqa.com".to_string()),
+            ..MailOptions::default()
+        };
+        assert!(validate_recipients(&addresses(&["owner@storiqa.com", "admin@storiqa.com"]), &options).is_ok());
+        assert!(validate_recipients(&addresses(&["owner@storiqa.com"]), &MailOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        let options = MailOptions {
+            cc: addresses(&["manager@storiqa.com", "manager"]),
+            bcc: addresses(&["@storiqa.com"]),
+            reply_to: Some("noreply at storiqa.com".to_string()),
+            ..MailOptions::default()
+        };
+        let to = addresses(&["owner@storiqa.com"]);
+        assert_eq!(codes(validate_recipients(&to, &options), "cc"), vec!["email"]);
+        assert_eq!(codes(validate_recipients(&to, &options), "bcc"), vec!["email"]);
+        assert_eq!(codes(validate_recipients(&to, &options), "reply_to"), vec!["email"]);
+        assert!(codes(validate_recipients(&to, &options), "to").is_empty());
+    }
+
+    #[test]
+    fn test_recipient_count() {
+        assert_eq!(codes(validate_recipients(&[], &MailOptions::default()), "to"), vec!["required"]);
+
+        let to = (0..MAX_RECIPIENTS).map(|i| format!("user{}@storiqa.com", i)).collect::<Vec<_>>();
+        assert!(validate_recipients(&to, &MailOptions::default()).is_ok());
+        let options = MailOptions {
+            bcc: addresses(&["support@storiqa.com"]),
+            ..MailOptions::default()
+        };
+        assert_eq!(codes(validate_recipients(&to, &options), "to"), vec!["count"]);
+    }
+}
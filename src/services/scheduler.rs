@@ -0,0 +1,190 @@
+//! Scheduled emails wait in the outbox until their send time. The scheduler moves
+//! them to the queue of the outbox worker, until then they can be cancelled.
+use std::borrow::Cow;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+use validator::{ValidationError, ValidationErrors};
+
+use config::SchedulerConf;
+use errors::Error;
+use models::{normalize_email, NewOutboxAttempt, NotificationCategory, NotificationStatus, OutboxMessage, OutboxStatus};
+use repos::{NotificationPreferencesRepo, NotificationsRepo, OutboxRepo, ReposFactory, SuppressionsRepo};
+use services::types::{Service, ServiceFuture};
+
+pub trait SchedulerService {
+    /// Move scheduled emails which are due to the outbox queue, emails to recipients suppressed
+    /// or opted out of their category since they were scheduled are cancelled
+    fn release_scheduled_emails(self) -> ServiceFuture<()>;
+    /// Cancel the email which is still waiting for its send time
+    fn cancel_scheduled_email(self, message_id: Uuid) -> ServiceFuture<OutboxMessage>;
+}
+
+impl<T, M, F> SchedulerService for Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    fn release_scheduled_emails(self) -> ServiceFuture<()> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        self.spawn_on_pool(move |conn| {
+            let outbox_repo = repo_factory.create_outbox_repo_with_sys_acl(&*conn);
+            let notifications_repo = repo_factory.create_notifications_repo_with_sys_acl(&*conn);
+            let suppressions_repo = repo_factory.create_suppressions_repo_with_sys_acl(&*conn);
+            let preferences_repo = repo_factory.create_notification_preferences_repo_with_sys_acl(&*conn);
+            conn.transaction::<(), FailureError, _>(move || {
+                for message in outbox_repo.release_scheduled()? {
+                    match blocked_reason(&message, &*notifications_repo, &*suppressions_repo, &*preferences_repo)? {
+                        Some(reason) => cancel_blocked(&*outbox_repo, &*notifications_repo, &message, reason)?,
+                        None => info!("Scheduled outbox message {} released to the queue", message.id),
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service SchedulerService, release_scheduled_emails endpoint error occurred.")
+                    .into()
+            })
+        })
+    }
+
+    fn cancel_scheduled_email(self, message_id: Uuid) -> ServiceFuture<OutboxMessage> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        self.spawn_on_pool(move |conn| {
+            let outbox_repo = repo_factory.create_outbox_repo(&*conn, user_id);
+            let notifications_repo = repo_factory.create_notifications_repo(&*conn, user_id);
+            conn.transaction::<OutboxMessage, FailureError, _>(move || {
+                let message = outbox_repo
+                    .cancel(message_id)?
+                    .ok_or_else(|| format_err!("Scheduled email {} not found", message_id).context(Error::NotFound))?;
+                notifications_repo.update_status(message_id, NotificationStatus::Cancelled)?;
+                info!("Scheduled outbox message {} cancelled", message.id);
+                Ok(message)
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service SchedulerService, cancel_scheduled_email endpoint error occurred.")
+                    .into()
+            })
+        })
+    }
+}
+
+/// Reason the released email mustn't be sent, its recipients could have been suppressed
+/// or opted out of its category while it was waiting for its send time
+fn blocked_reason(
+    message: &OutboxMessage,
+    notifications_repo: &NotificationsRepo,
+    suppressions_repo: &SuppressionsRepo,
+    preferences_repo: &NotificationPreferencesRepo,
+) -> Result<Option<String>, FailureError> {
+    // broken payloads are dead-lettered by the outbox worker
+    let payload = match message.sendgrid_payload() {
+        Ok(payload) => payload,
+        Err(_) => return Ok(None),
+    };
+
+    let recipients = payload
+        .get_address_list()
+        .iter()
+        .map(|email| normalize_email(email))
+        .collect::<Vec<_>>();
+    if let Some(suppression) = suppressions_repo.find_any(&recipients)?.into_iter().next() {
+        return Ok(Some(format!(
+            "Recipient {} is suppressed ({})",
+            suppression.email, suppression.reason
+        )));
+    }
+
+    let category = match notifications_repo.get(message.id)?.and_then(|notification| notification.template) {
+        Some(template) => NotificationCategory::of_template(&template),
+        None => return Ok(None),
+    };
+    if !category.is_optional() {
+        return Ok(None);
+    }
+    let to = payload
+        .personalizations
+        .iter()
+        .flat_map(|personalization| personalization.to.iter())
+        .map(|address| normalize_email(&address.email))
+        .collect::<Vec<_>>();
+    for email in &to {
+        let preference = preferences_repo.find(email, category)?;
+        if preference.map(|preference| preference.enabled).unwrap_or(true) {
+            return Ok(None);
+        }
+    }
+    Ok(if to.is_empty() {
+        None
+    } else {
+        Some(format!("Recipients disabled {} emails", category))
+    })
+}
+
+/// Cancels the released email, the reason is kept as its last attempt
+fn cancel_blocked(
+    outbox_repo: &OutboxRepo,
+    notifications_repo: &NotificationsRepo,
+    message: &OutboxMessage,
+    reason: String,
+) -> Result<(), FailureError> {
+    info!("Scheduled outbox message {} cancelled: {}", message.id, reason);
+    let attempt = NewOutboxAttempt {
+        message_id: message.id,
+        succeeded: false,
+        error: Some(reason),
+        provider: None,
+    };
+    outbox_repo.record_attempt(attempt, OutboxStatus::Cancelled, SystemTime::now())?;
+    notifications_repo.update_status(message.id, NotificationStatus::Cancelled)?;
+    Ok(())
+}
+
+/// Validates send time of the email, errors are keyed by `send_at`
+pub fn validate_send_at(send_at: Option<DateTime<Utc>>, conf: &SchedulerConf) -> Result<(), ValidationErrors> {
+    match send_at {
+        Some(send_at) if send_at > Utc::now() + ChronoDuration::days(conf.max_delay_days as i64) => {
+            let mut error = ValidationError::new("range");
+            error.message = Some(Cow::from(format!(
+                "Emails can be scheduled at most {} days ahead",
+                conf.max_delay_days
+            )));
+            let mut errors = ValidationErrors::new();
+            errors.add("send_at", error);
+            Err(errors)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_send_at() {
+        let conf = SchedulerConf {
+            poll_interval_ms: 1000,
+            max_delay_days: 30,
+        };
+        assert!(validate_send_at(None, &conf).is_ok());
+        assert!(validate_send_at(Some(Utc::now() - ChronoDuration::days(1)), &conf).is_ok());
+        assert!(validate_send_at(Some(Utc::now() + ChronoDuration::days(29)), &conf).is_ok());
+
+        let errors = validate_send_at(Some(Utc::now() + ChronoDuration::days(31)), &conf).unwrap_err();
+        let codes = errors.inner()["send_at"]
+            .iter()
+            .map(|error| error.code.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(codes, vec!["range"]);
+    }
+}
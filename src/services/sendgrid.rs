@@ -1,9 +1,9 @@
 use failure::Fail;
 use futures::prelude::*;
 use hyper::header::{Authorization, Bearer, ContentType};
-use hyper::{mime, Headers, Method};
+use hyper::{mime, Headers, Method, StatusCode};
 
-use stq_http::client::ClientHandle;
+use stq_http::client::{ClientHandle, Error as HttpError};
 
 use config::SendGridConf;
 use errors::Error;
@@ -41,8 +41,21 @@ impl SendgridService for SendgridServiceImpl {
             .and_then(move |body| {
                 client_handle
                     .request::<()>(Method::Post, url, Some(body), Some(headers))
-                    .map_err(|e| e.context(Error::HttpClient).into())
+                    .map_err(|e| {
+                        let kind = classify_http_error(&e);
+                        e.context(kind).into()
+                    })
             });
         Box::new(res)
     }
 }
+
+/// Only a rejected payload means the message itself won't be accepted. Auth and
+/// account failures (401, 403, 404), rate limiting and server errors are provider
+/// failures: they are retried and count towards the circuit breaker.
+fn classify_http_error(e: &HttpError) -> Error {
+    match *e {
+        HttpError::Api(status, _) if status == StatusCode::BadRequest || status == StatusCode::PayloadTooLarge => Error::ProviderRejected,
+        _ => Error::HttpClient,
+    }
+}
@@ -0,0 +1,325 @@
+//! SendGrid event webhook. Events update the status of the notifications
+//! and suppress addresses which hard-bounced or complained about spam.
+
+use base64;
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Verifier;
+use r2d2::ManageConnection;
+use serde_json::{self, Value};
+
+use config::MailProvider;
+use errors::Error;
+use models::{normalize_email, NewNotificationEvent, NewSuppression, SendGridEvent};
+use repos::{NotificationsRepo, ReposFactory, SuppressionsRepo};
+use services::types::ServiceFuture;
+use services::Service;
+
+/// Base64 encoded ECDSA signature of the timestamp followed by the request body
+pub const SIGNATURE_HEADER: &'static str = "X-Twilio-Email-Event-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &'static str = "X-Twilio-Email-Event-Webhook-Timestamp";
+/// Signed requests older or newer than this are refused, so captured ones can't be replayed
+const TIMESTAMP_TOLERANCE_SECS: i64 = 300;
+
+/// Signed webhook request
+#[derive(Clone, Debug)]
+pub struct SignedEvents {
+    pub signature: Option<String>,
+    pub timestamp: Option<String>,
+    pub body: String,
+}
+
+pub trait SendGridEventsService {
+    /// Apply batch of events posted by SendGrid to the delivery log and the suppression list
+    fn ingest_sendgrid_events(self, events: SignedEvents) -> ServiceFuture<()>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > SendGridEventsService for Service<T, M, F>
+{
+    fn ingest_sendgrid_events(self, events: SignedEvents) -> ServiceFuture<()> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let public_key = self.static_context.config.sendgrid.webhook_public_key.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let notifications_repo = repo_factory.create_notifications_repo_with_sys_acl(&*conn);
+            let suppressions_repo = repo_factory.create_suppressions_repo_with_sys_acl(&*conn);
+            verify_events(public_key.as_ref().map(String::as_str), &events, Utc::now().timestamp())
+                .and_then(|_| parse_sendgrid_events(&events.body))
+                .and_then(|parsed| {
+                    debug!("Ingesting {} SendGrid events", parsed.len());
+                    conn.transaction::<(), FailureError, _>(|| {
+                        for event in &parsed {
+                            apply_event(&*notifications_repo, &*suppressions_repo, event)?;
+                        }
+                        Ok(())
+                    })
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service SendGridEventsService, ingest_sendgrid_events endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+}
+
+/// Checks the signature and that the signed timestamp is within the tolerance of `now`, in seconds since the epoch
+fn verify_events(public_key: Option<&str>, events: &SignedEvents, now: i64) -> Result<(), FailureError> {
+    let public_key = public_key.ok_or_else(|| {
+        error!("SendGrid webhook public key is not configured, refusing events");
+        format_err!("SendGrid webhook public key is not configured").context(Error::Forbidden)
+    })?;
+    match (&events.signature, &events.timestamp) {
+        (&Some(ref signature), &Some(ref timestamp)) if verify_signature(public_key, signature, timestamp, events.body.as_bytes()) => {
+            match timestamp.trim().parse::<i64>() {
+                Ok(signed_at) if (now - signed_at).abs() <= TIMESTAMP_TOLERANCE_SECS => Ok(()),
+                _ => Err(format_err!("Stale SendGrid webhook timestamp {}", timestamp)
+                    .context(Error::Forbidden)
+                    .into()),
+            }
+        }
+        _ => Err(format_err!("Invalid SendGrid webhook signature").context(Error::Forbidden).into()),
+    }
+}
+
+/// Checks the signature of the signed event webhook, see
+/// https://sendgrid.com/docs/for-developers/tracking-events/getting-started-event-webhook-security-features/
+pub fn verify_signature(public_key: &str, signature: &str, timestamp: &str, body: &[u8]) -> bool {
+    let verify = || -> Result<bool, FailureError> {
+        let key = PKey::public_key_from_der(&base64::decode(public_key.trim())?)?;
+        let signature = base64::decode(signature.trim())?;
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+        verifier.update(timestamp.as_bytes())?;
+        verifier.update(body)?;
+        Ok(verifier.verify(&signature)?)
+    };
+    verify().unwrap_or_else(|e| {
+        warn!("Couldn't verify SendGrid webhook signature: {}", e);
+        false
+    })
+}
+
+/// Parses event batch. Events of unknown kinds or with missing fields are skipped,
+/// otherwise SendGrid would retry the whole batch.
+pub fn parse_sendgrid_events(body: &str) -> Result<Vec<SendGridEvent>, FailureError> {
+    let values = serde_json::from_str::<Vec<Value>>(body)
+        .map_err(|e| -> FailureError { e.context("Couldn't parse SendGrid event batch").context(Error::Parse).into() })?;
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match serde_json::from_value::<SendGridEvent>(value.clone()) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                warn!("Skipping SendGrid event {}: {}", value, e);
+                None
+            }
+        })
+        .collect())
+}
+
+fn apply_event(
+    notifications_repo: &NotificationsRepo,
+    suppressions_repo: &SuppressionsRepo,
+    event: &SendGridEvent,
+) -> Result<(), FailureError> {
+    if let Some(reason) = event.suppression_reason() {
+        info!("Suppressing {} after SendGrid {:?} event", event.email, event.event);
+        suppressions_repo.upsert(NewSuppression {
+            email: normalize_email(&event.email),
+            reason,
+            source: MailProvider::Sendgrid.to_string(),
+        })?;
+    }
+
+    let notification = match (event.notification_id(), event.message_id()) {
+        (Some(id), _) => notifications_repo.get(id)?,
+        (None, Some(message_id)) => notifications_repo.find_by_provider_message_id(message_id)?,
+        (None, None) => None,
+    };
+    let mut notification = match notification {
+        Some(notification) => notification,
+        None => {
+            debug!("SendGrid event {:?} doesn't match any notification", event.sg_event_id);
+            return Ok(());
+        }
+    };
+
+    if let (None, Some(message_id)) = (notification.provider_message_id.clone(), event.message_id()) {
+        notification = notifications_repo.set_provider_message_id(notification.id, message_id)?;
+    }
+
+    if let Some(status) = event.notification_status() {
+        let recorded = notifications_repo.add_event(NewNotificationEvent {
+            notification_id: notification.id,
+            status,
+            provider_event_id: event.sg_event_id.clone(),
+            occurred_at: event.occurred_at(),
+        })?;
+        if recorded.is_some() && notification.status.is_superseded_by(status) {
+            notifications_repo.update_status(notification.id, status)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+
+    use super::*;
+    use models::{NotificationStatus, SendGridEventKind, SuppressionReason};
+
+    const EVENTS: &'static str = include_str!("../../tests/fixtures/sendgrid/events.json");
+    const UNKNOWN_EVENTS: &'static str = include_str!("../../tests/fixtures/sendgrid/events_unknown.json");
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    fn sign(key: &PKey<Private>, timestamp: &str, body: &str) -> String {
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer.update(timestamp.as_bytes()).unwrap();
+        signer.update(body.as_bytes()).unwrap();
+        base64::encode(&signer.sign_to_vec().unwrap())
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let events = parse_sendgrid_events(EVENTS).unwrap();
+        assert_eq!(events.len(), 10);
+
+        let kinds = events.iter().map(|event| event.event).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                SendGridEventKind::Processed,
+                SendGridEventKind::Deferred,
+                SendGridEventKind::Delivered,
+                SendGridEventKind::Open,
+                SendGridEventKind::Click,
+                SendGridEventKind::Bounce,
+                SendGridEventKind::Bounce,
+                SendGridEventKind::Dropped,
+                SendGridEventKind::SpamReport,
+                SendGridEventKind::GroupUnsubscribe,
+            ]
+        );
+
+        let delivered = &events[2];
+        assert_eq!(delivered.message_id(), Some("14c5d75ce93.dfd.64b469"));
+        assert_eq!(
+            delivered.notification_id().map(|id| id.to_string()),
+            Some("936da01f-9abd-4d9d-80c7-02af85c822a8".to_string())
+        );
+        assert_eq!(delivered.sg_event_id, Some("rWVYmVk90MjZJ9iohOBa3w==".to_string()));
+    }
+
+    #[test]
+    fn test_event_statuses_and_suppressions() {
+        let events = parse_sendgrid_events(EVENTS).unwrap();
+        let statuses = events.iter().map(|event| event.notification_status()).collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                None,
+                None,
+                Some(NotificationStatus::Delivered),
+                Some(NotificationStatus::Opened),
+                Some(NotificationStatus::Clicked),
+                Some(NotificationStatus::Bounced),
+                Some(NotificationStatus::Bounced),
+                Some(NotificationStatus::Dropped),
+                Some(NotificationStatus::Complained),
+                None,
+            ]
+        );
+
+        let suppressions = events
+            .iter()
+            .filter_map(|event| event.suppression_reason().map(|reason| (event.email.as_str(), reason)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            suppressions,
+            vec![
+                ("nobody@storiqa.com", SuppressionReason::HardBounce),
+                ("Angry@Storiqa.com", SuppressionReason::Complaint),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_skips_unknown_and_malformed_events() {
+        let events = parse_sendgrid_events(UNKNOWN_EVENTS).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, SendGridEventKind::Delivered);
+        assert_eq!(events[0].message_id(), Some("W86EgYT6SQKk0lRflfLRsA"));
+        assert_eq!(events[0].notification_id(), None);
+
+        assert!(parse_sendgrid_events("{\"event\": \"open\"}").is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let key = key();
+        let public_key = base64::encode(&key.public_key_to_der().unwrap());
+        let timestamp = "1547460000";
+        let signature = sign(&key, timestamp, EVENTS);
+
+        assert!(verify_signature(&public_key, &signature, timestamp, EVENTS.as_bytes()));
+        assert!(!verify_signature(&public_key, &signature, "1547460001", EVENTS.as_bytes()));
+        assert!(!verify_signature(&public_key, &signature, timestamp, UNKNOWN_EVENTS.as_bytes()));
+        assert!(!verify_signature(&public_key, "garbage", timestamp, EVENTS.as_bytes()));
+
+        let other_key = base64::encode(&self::key().public_key_to_der().unwrap());
+        assert!(!verify_signature(&other_key, &signature, timestamp, EVENTS.as_bytes()));
+    }
+
+    #[test]
+    fn test_unsigned_events_are_refused() {
+        let key = key();
+        let public_key = base64::encode(&key.public_key_to_der().unwrap());
+        let signed = SignedEvents {
+            signature: Some(sign(&key, "1547460000", EVENTS)),
+            timestamp: Some("1547460000".to_string()),
+            body: EVENTS.to_string(),
+        };
+        assert!(verify_events(Some(public_key.as_str()), &signed, 1547460010).is_ok());
+        assert!(verify_events(None, &signed, 1547460010).is_err());
+
+        let unsigned = SignedEvents { signature: None, ..signed };
+        assert!(verify_events(Some(public_key.as_str()), &unsigned, 1547460010).is_err());
+    }
+
+    #[test]
+    fn test_stale_events_are_refused() {
+        let key = key();
+        let public_key = base64::encode(&key.public_key_to_der().unwrap());
+        let signed = SignedEvents {
+            signature: Some(sign(&key, "1547460000", EVENTS)),
+            timestamp: Some("1547460000".to_string()),
+            body: EVENTS.to_string(),
+        };
+        assert!(verify_events(Some(public_key.as_str()), &signed, 1547460000 + TIMESTAMP_TOLERANCE_SECS).is_ok());
+        assert!(verify_events(Some(public_key.as_str()), &signed, 1547460000 + TIMESTAMP_TOLERANCE_SECS + 1).is_err());
+        assert!(verify_events(Some(public_key.as_str()), &signed, 1547460000 - TIMESTAMP_TOLERANCE_SECS - 1).is_err());
+
+        let garbage = SignedEvents {
+            signature: Some(sign(&key, "yesterday", EVENTS)),
+            timestamp: Some("yesterday".to_string()),
+            body: EVENTS.to_string(),
+        };
+        assert!(verify_events(Some(public_key.as_str()), &garbage, 1547460000).is_err());
+    }
+}
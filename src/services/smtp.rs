@@ -0,0 +1,142 @@
+use base64;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures_cpupool::CpuPool;
+use lettre::smtp::authentication::Credentials;
+use lettre::smtp::error::Error as SmtpError;
+use lettre::smtp::response::Category;
+use lettre::smtp::{ClientSecurity, ClientTlsParameters, SmtpClient};
+use lettre::Transport;
+use lettre_email::{Email as LettreEmail, EmailBuilder, Mailbox, MimeMultipartType, PartBuilder};
+use mime::{TEXT_HTML, TEXT_PLAIN};
+use native_tls::TlsConnector;
+
+use config::SmtpConf;
+use errors::Error;
+use models::{Address, SendGridAttachment, SendGridPayload};
+use services::sendgrid::SendgridService;
+use services::types::ServiceFuture;
+
+/// Delivers emails to an SMTP server, e.g. a local MailHog in development
+pub struct SmtpServiceImpl {
+    pub config: SmtpConf,
+    pub cpu_pool: CpuPool,
+}
+
+impl SendgridService for SmtpServiceImpl {
+    fn send(&self, payload: SendGridPayload) -> ServiceFuture<()> {
+        let config = self.config.clone();
+        Box::new(self.cpu_pool.spawn_fn(move || {
+            let email = build_email(payload)?;
+            let mut transport = create_client(&config)?.transport();
+            let result = transport.send(email.into()).map(|_| ()).map_err(|e| {
+                let kind = classify_smtp_error(&e);
+                e.context(kind).into()
+            });
+            transport.close();
+            result
+        }))
+    }
+}
+
+fn create_client(config: &SmtpConf) -> Result<SmtpClient, FailureError> {
+    let security = if config.starttls {
+        let connector = TlsConnector::builder()
+            .build()
+            .map_err(|e| e.context("Couldn't create TLS connector").context(Error::SmtpClient))?;
+        ClientSecurity::Required(ClientTlsParameters::new(config.host.clone(), connector))
+    } else {
+        ClientSecurity::None
+    };
+
+    let client = SmtpClient::new((config.host.as_str(), config.port), security).map_err(|e| {
+        e.context(format!("Couldn't connect to SMTP server {}:{}", config.host, config.port))
+            .context(Error::SmtpClient)
+    })?;
+
+    Ok(match (config.username.clone(), config.password.clone()) {
+        (Some(username), Some(password)) => client.credentials(Credentials::new(username, password)),
+        _ => client,
+    })
+}
+
+fn build_email(payload: SendGridPayload) -> Result<LettreEmail, FailureError> {
+    let mut builder = EmailBuilder::new()
+        .from((payload.from.email.clone(), payload.from.name.clone().unwrap_or_default()))
+        .subject(payload.subject.clone());
+
+    for p in &payload.personalizations {
+        for address in &p.to {
+            builder = builder.to(mailbox(address));
+        }
+        for address in &p.cc {
+            builder = builder.cc(mailbox(address));
+        }
+        for address in &p.bcc {
+            builder = builder.bcc(mailbox(address));
+        }
+    }
+    if let Some(ref address) = payload.reply_to {
+        builder = builder.reply_to(mailbox(address));
+    }
+
+    for (name, value) in &payload.headers {
+        builder = builder.header((name.as_str(), value.as_str()));
+    }
+
+    let html = payload.content.iter().find(|content| content.type_field == TEXT_HTML.to_string());
+    let text = payload.content.iter().find(|content| content.type_field == TEXT_PLAIN.to_string());
+    builder = match (html, text) {
+        (Some(html), Some(text)) => builder.alternative(html.value.clone(), text.value.clone()),
+        (Some(html), None) => builder.html(html.value.clone()),
+        (None, Some(text)) => builder.text(text.value.clone()),
+        (None, None) => builder,
+    };
+
+    if !payload.attachments.is_empty() {
+        builder = builder.message_type(MimeMultipartType::Mixed);
+    }
+    for attachment in &payload.attachments {
+        builder = builder.child(attachment_part(attachment)?.build());
+    }
+
+    builder
+        .build()
+        .map_err(|e| e.context("Couldn't build email for SMTP").context(Error::Parse).into())
+}
+
+fn mailbox(address: &Address) -> Mailbox {
+    match address.name {
+        Some(ref name) => Mailbox::new_with_name(name.clone(), address.email.clone()),
+        None => Mailbox::new(address.email.clone()),
+    }
+}
+
+/// MIME part of the attachment, the content is wrapped into lines as SMTP limits their length
+fn attachment_part(attachment: &SendGridAttachment) -> Result<PartBuilder, FailureError> {
+    let content = base64::decode(&attachment.content.chars().filter(|c| !c.is_whitespace()).collect::<String>()).map_err(|e| {
+        e.context(format!("Couldn't decode attachment {}", attachment.filename))
+            .context(Error::Parse)
+    })?;
+    let part = PartBuilder::new()
+        .body(base64::encode_config(&content, base64::MIME))
+        .header(("Content-Type", attachment.type_field.as_str()))
+        .header((
+            "Content-Disposition",
+            format!("{}; filename=\"{}\"", attachment.disposition, attachment.filename),
+        ))
+        .header(("Content-Transfer-Encoding", "base64"));
+    Ok(match attachment.content_id {
+        Some(ref content_id) => part.header(("Content-ID", format!("<{}>", content_id))),
+        None => part,
+    })
+}
+
+/// Permanent mail system replies (55x) mean the server won't ever accept the message.
+/// Other permanent replies, e.g. failed authentication (53x), are server failures.
+fn classify_smtp_error(e: &SmtpError) -> Error {
+    match *e {
+        SmtpError::Permanent(ref response) if response.code.category == Category::MailSystem => Error::ProviderRejected,
+        _ => Error::SmtpClient,
+    }
+}
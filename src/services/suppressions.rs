@@ -0,0 +1,78 @@
+//! Suppressions Services, admins manage addresses emails are never sent to
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use errors::Error;
+use models::{normalize_email, CreateSuppression, NewSuppression, Suppression, SuppressionReason, MANUAL_SUPPRESSION_SOURCE};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait SuppressionsService {
+    /// List suppressed addresses, newest first
+    fn list_suppressions(self, email: Option<String>, offset: i64, count: i64) -> ServiceFuture<Vec<Suppression>>;
+    /// Suppress the address manually
+    fn add_suppression(self, payload: CreateSuppression) -> ServiceFuture<Suppression>;
+    /// Remove the address from the list, emails are sent to it again
+    fn delete_suppression(self, email: String) -> ServiceFuture<Suppression>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > SuppressionsService for Service<T, M, F>
+{
+    fn list_suppressions(self, email: Option<String>, offset: i64, count: i64) -> ServiceFuture<Vec<Suppression>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let suppressions_repo = repo_factory.create_suppressions_repo(&*conn, current_uid);
+            let email = email.map(|email| normalize_email(&email));
+            suppressions_repo
+                .list(email.as_ref().map(String::as_str), offset, count)
+                .map_err(|e: FailureError| e.context("Service suppressions, list_suppressions endpoint error occurred.").into())
+        })
+    }
+
+    fn add_suppression(self, payload: CreateSuppression) -> ServiceFuture<Suppression> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let suppressions_repo = repo_factory.create_suppressions_repo(&*conn, current_uid);
+            suppressions_repo
+                .upsert(NewSuppression {
+                    email: normalize_email(&payload.email),
+                    reason: payload.reason.unwrap_or(SuppressionReason::Manual),
+                    source: MANUAL_SUPPRESSION_SOURCE.to_string(),
+                })
+                .map_err(|e: FailureError| e.context("Service suppressions, add_suppression endpoint error occurred.").into())
+        })
+    }
+
+    fn delete_suppression(self, email: String) -> ServiceFuture<Suppression> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let suppressions_repo = repo_factory.create_suppressions_repo(&*conn, current_uid);
+            let email = normalize_email(&email);
+            suppressions_repo
+                .delete(&email)
+                .and_then(|suppression| {
+                    suppression.ok_or_else(|| format_err!("Suppression of {} not found", email).context(Error::NotFound).into())
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service suppressions, delete_suppression endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+}
@@ -0,0 +1,404 @@
+//! Handlebars helpers available in all templates:
+//!
+//! * `{{format_price amount "USD" decimals=2}}` - `$1,234.50`
+//! * `{{format_date created_at "%d %B %Y %H:%M" tz="+03:00" locale="ru"}}` - `01 декабря 2018 13:00`
+//! * `{{url_join cluster_url "store" store_id}}` - `https://storiqa.com/store/1`
+//! * `{{pluralize count "item" "items"}}` - `items`, with three forms the Slavic rule is used
+//! * `{{uppercase order_state}}` - `PAID`
+use std::fmt::Write;
+
+use chrono::{DateTime, Datelike, FixedOffset, TimeZone, Utc};
+use handlebars::{Context, Handlebars, Helper, HelperResult, JsonRender, Output, RenderContext, RenderError};
+use serde_json::Value;
+
+/// Registers the helpers in the registry
+pub fn register_helpers(handlebars: &mut Handlebars) {
+    handlebars.register_helper("format_price", Box::new(format_price_helper));
+    handlebars.register_helper("format_date", Box::new(format_date_helper));
+    handlebars.register_helper("url_join", Box::new(url_join_helper));
+    handlebars.register_helper("pluralize", Box::new(pluralize_helper));
+    handlebars.register_helper("uppercase", Box::new(uppercase_helper));
+}
+
+fn format_price_helper(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut Output) -> HelperResult {
+    let amount = param_f64(h, 0, "format_price")?;
+    let currency = h.param(1).map(|param| param.value().render()).unwrap_or_default();
+    let decimals = match h.hash_get("decimals") {
+        Some(decimals) => decimals
+            .value()
+            .as_u64()
+            .ok_or_else(|| RenderError::new("format_price: decimals must be a positive number"))? as usize,
+        None => 2,
+    };
+    out.write(&format_price(amount, &currency, decimals))?;
+    Ok(())
+}
+
+fn format_date_helper(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut Output) -> HelperResult {
+    let value = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("format_date: date is required"))?
+        .value();
+    let date = parse_date(value).ok_or_else(|| RenderError::new(format!("format_date: couldn't parse date {}", value)))?;
+    let offset = match h.hash_get("tz") {
+        Some(tz) => {
+            let tz = tz.value().render();
+            parse_offset(&tz).ok_or_else(|| RenderError::new(format!("format_date: unknown timezone {}", tz)))?
+        }
+        None => FixedOffset::east(0),
+    };
+    let locale = h.hash_get("locale").map(|locale| locale.value().render()).unwrap_or_default();
+    let format = h
+        .param(1)
+        .map(|format| format.value().render())
+        .unwrap_or_else(|| default_date_format(&locale).to_string());
+    let date = date.with_timezone(&offset);
+    let format = match date_names(&locale) {
+        Some(names) => localize_format(&format, &date, names),
+        None => format,
+    };
+    // chrono reports unknown format specifiers only while writing
+    let mut formatted = String::new();
+    write!(formatted, "{}", date.format(&format))
+        .map_err(|_| RenderError::new(format!("format_date: invalid format {}", format)))?;
+    out.write(&formatted)?;
+    Ok(())
+}
+
+fn url_join_helper(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut Output) -> HelperResult {
+    let parts = h.params().iter().map(|param| param.value().render()).collect::<Vec<_>>();
+    out.write(&url_join(&parts))?;
+    Ok(())
+}
+
+fn pluralize_helper(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut Output) -> HelperResult {
+    let count = param_f64(h, 0, "pluralize")?;
+    let forms = h.params().iter().skip(1).map(|param| param.value().render()).collect::<Vec<_>>();
+    if forms.is_empty() {
+        return Err(RenderError::new("pluralize: singular form is required"));
+    }
+    out.write(&pluralize(count.abs() as u64, &forms))?;
+    Ok(())
+}
+
+fn uppercase_helper(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut Output) -> HelperResult {
+    let value = h.param(0).map(|param| param.value().render()).unwrap_or_default();
+    out.write(&value.to_uppercase())?;
+    Ok(())
+}
+
+fn param_f64(h: &Helper, index: usize, helper: &str) -> Result<f64, RenderError> {
+    let value = h
+        .param(index)
+        .ok_or_else(|| RenderError::new(format!("{}: param {} is required", helper, index)))?
+        .value();
+    let number = match *value {
+        Value::Number(ref number) => number.as_f64(),
+        Value::String(ref string) => string.trim().parse().ok(),
+        _ => None,
+    };
+    number.ok_or_else(|| RenderError::new(format!("{}: {} is not a number", helper, value)))
+}
+
+/// Amount with thousands separators, the currency symbol is used for known currencies
+fn format_price(amount: f64, currency: &str, decimals: usize) -> String {
+    let formatted = format!("{:.*}", decimals, amount.abs());
+    let (integer, fraction) = match formatted.find('.') {
+        Some(position) => formatted.split_at(position),
+        None => (formatted.as_str(), ""),
+    };
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let sign = if amount < 0.0 && formatted.chars().any(|c| c != '0' && c != '.') {
+        "-"
+    } else {
+        ""
+    };
+    let number = format!("{}{}", grouped, fraction);
+
+    match currency.to_uppercase().as_str() {
+        "" => format!("{}{}", sign, number),
+        "USD" => format!("{}${}", sign, number),
+        "EUR" => format!("{}€{}", sign, number),
+        "GBP" => format!("{}£{}", sign, number),
+        "RUB" => format!("{}{} ₽", sign, number),
+        code => format!("{}{} {}", sign, number, code),
+    }
+}
+
+/// Accepts RFC 3339 strings, unix timestamps and serialized `SystemTime`
+fn parse_date(value: &Value) -> Option<DateTime<Utc>> {
+    match *value {
+        Value::String(ref string) => DateTime::parse_from_rfc3339(string).ok().map(|date| date.with_timezone(&Utc)),
+        Value::Number(ref number) => number.as_i64().and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+        Value::Object(ref object) => object
+            .get("secs_since_epoch")
+            .and_then(|secs| secs.as_i64())
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+        _ => None,
+    }
+}
+
+/// Parses `UTC`, `Z` and offsets like `+03:00` or `-0500`
+fn parse_offset(tz: &str) -> Option<FixedOffset> {
+    let tz = tz.trim();
+    if tz.eq_ignore_ascii_case("utc") || tz == "Z" {
+        return Some(FixedOffset::east(0));
+    }
+    let sign = match tz.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return None,
+    };
+    let digits = tz[1..].replace(':', "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    Some(FixedOffset::east(sign * (hours * 3600 + minutes * 60)))
+}
+
+/// Language of the locale, e.g. `ru` of `ru-RU`
+fn language(locale: &str) -> &str {
+    locale.split(|c| c == '-' || c == '_').next().unwrap_or("")
+}
+
+fn default_date_format(locale: &str) -> &'static str {
+    match language(locale) {
+        "en" => "%B %-d, %Y",
+        "ru" | "de" => "%d.%m.%Y",
+        _ => "%Y-%m-%d",
+    }
+}
+
+/// Month and weekday names of the language, chrono writes English ones only
+struct DateNames {
+    months: [&'static str; 12],
+    short_months: [&'static str; 12],
+    weekdays: [&'static str; 7],
+    short_weekdays: [&'static str; 7],
+}
+
+/// Months are in the genitive case, as they follow the day, e.g. `1 декабря 2018`
+const RU_DATE_NAMES: DateNames = DateNames {
+    months: [
+        "января",
+        "февраля",
+        "марта",
+        "апреля",
+        "мая",
+        "июня",
+        "июля",
+        "августа",
+        "сентября",
+        "октября",
+        "ноября",
+        "декабря",
+    ],
+    short_months: ["янв", "фев", "мар", "апр", "мая", "июн", "июл", "авг", "сен", "окт", "ноя", "дек"],
+    weekdays: ["понедельник", "вторник", "среда", "четверг", "пятница", "суббота", "воскресенье"],
+    short_weekdays: ["пн", "вт", "ср", "чт", "пт", "сб", "вс"],
+};
+
+const DE_DATE_NAMES: DateNames = DateNames {
+    months: [
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ],
+    short_months: ["Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez"],
+    weekdays: ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"],
+    short_weekdays: ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+};
+
+/// Names for the locale, `None` if chrono's English names are used
+fn date_names(locale: &str) -> Option<&'static DateNames> {
+    match language(locale) {
+        "ru" => Some(&RU_DATE_NAMES),
+        "de" => Some(&DE_DATE_NAMES),
+        _ => None,
+    }
+}
+
+/// Replaces month and weekday specifiers (`%B`, `%b`, `%h`, `%A`, `%a`) with the names
+/// of the date in the language, other specifiers are left to chrono
+fn localize_format(format: &str, date: &DateTime<FixedOffset>, names: &DateNames) -> String {
+    let month = date.month0() as usize;
+    let weekday = date.weekday().num_days_from_monday() as usize;
+    let mut localized = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            localized.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('B') => localized.push_str(names.months[month]),
+            Some('b') | Some('h') => localized.push_str(names.short_months[month]),
+            Some('A') => localized.push_str(names.weekdays[weekday]),
+            Some('a') => localized.push_str(names.short_weekdays[weekday]),
+            Some(next) => {
+                localized.push('%');
+                localized.push(next);
+            }
+            None => localized.push('%'),
+        }
+    }
+    localized
+}
+
+/// Joins URL parts with exactly one slash between them
+fn url_join(parts: &[String]) -> String {
+    let parts = parts.iter().filter(|part| !part.is_empty()).collect::<Vec<_>>();
+    let last = parts.len().saturating_sub(1);
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let part = if i > 0 { part.trim_left_matches('/') } else { part.as_str() };
+            if i < last {
+                part.trim_right_matches('/')
+            } else {
+                part
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `forms` are singular and plural, or the three Slavic forms, e.g. `товар`, `товара`, `товаров`
+fn pluralize(count: u64, forms: &[String]) -> String {
+    match forms.len() {
+        1 if count == 1 => forms[0].clone(),
+        1 => format!("{}s", forms[0]),
+        2 => {
+            if count == 1 {
+                forms[0].clone()
+            } else {
+                forms[1].clone()
+            }
+        }
+        _ => {
+            let (last, last_two) = (count % 10, count % 100);
+            if last == 1 && last_two != 11 {
+                forms[0].clone()
+            } else if last >= 2 && last <= 4 && (last_two < 12 || last_two > 14) {
+                forms[1].clone()
+            } else {
+                forms[2].clone()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::*;
+
+    fn render(template: &str, data: &str) -> Result<String, String> {
+        let mut handlebars = Handlebars::new();
+        register_helpers(&mut handlebars);
+        let data: Value = serde_json::from_str(data).unwrap();
+        handlebars.render_template(template, &data).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_format_price() {
+        assert_eq!(render("{{format_price total \"USD\"}}", r#"{"total": 1234.5}"#).unwrap(), "$1,234.50");
+        assert_eq!(render("{{format_price total \"rub\"}}", r#"{"total": "-999999.999"}"#).unwrap(), "-1,000,000.00 ₽");
+        assert_eq!(
+            render("{{format_price total \"STQ\" decimals=8}}", r#"{"total": 0.5}"#).unwrap(),
+            "0.50000000 STQ"
+        );
+        assert!(render("{{format_price total \"USD\"}}", r#"{"total": "free"}"#).is_err());
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(
+            render(
+                "{{format_date created_at \"%d.%m.%Y %H:%M\" tz=\"+03:00\"}}",
+                r#"{"created_at": "2018-12-01T10:00:00Z"}"#
+            )
+            .unwrap(),
+            "01.12.2018 13:00"
+        );
+        assert_eq!(
+            render("{{format_date created_at locale=\"en\"}}", r#"{"created_at": 1543658400}"#).unwrap(),
+            "December 1, 2018"
+        );
+        assert_eq!(
+            render(
+                "{{format_date created_at locale=\"ru\" tz=\"-05:00\"}}",
+                r#"{"created_at": {"secs_since_epoch": 1543622400, "nanos_since_epoch": 0}}"#
+            )
+            .unwrap(),
+            "30.11.2018"
+        );
+        assert!(render("{{format_date created_at tz=\"Mars\"}}", r#"{"created_at": 0}"#).is_err());
+        assert!(render("{{format_date created_at}}", r#"{"created_at": 9223372036854775807}"#).is_err());
+        assert!(render("{{format_date created_at \"%Q\"}}", r#"{"created_at": 0}"#).is_err());
+    }
+
+    #[test]
+    fn test_format_date_in_locale() {
+        let data = r#"{"created_at": "2018-12-01T10:00:00Z"}"#;
+        assert_eq!(
+            render("{{format_date created_at \"%A, %-d %B %Y\" locale=\"ru-RU\"}}", data).unwrap(),
+            "суббота, 1 декабря 2018"
+        );
+        assert_eq!(
+            render("{{format_date created_at \"%a %d. %b %Y, 100%%\" locale=\"de\"}}", data).unwrap(),
+            "Sa 01. Dez 2018, 100%"
+        );
+        assert_eq!(render("{{format_date created_at \"%B\" locale=\"fr\"}}", data).unwrap(), "December");
+    }
+
+    #[test]
+    fn test_url_join() {
+        assert_eq!(
+            render(
+                "{{url_join cluster_url \"/store/\" store_id \"products\"}}",
+                r#"{"cluster_url": "https://storiqa.com/", "store_id": 1}"#
+            )
+            .unwrap(),
+            "https://storiqa.com/store/1/products"
+        );
+    }
+
+    #[test]
+    fn test_pluralize() {
+        assert_eq!(render("{{pluralize count \"item\"}}", r#"{"count": 1}"#).unwrap(), "item");
+        assert_eq!(render("{{pluralize count \"item\"}}", r#"{"count": 0}"#).unwrap(), "items");
+        assert_eq!(render("{{pluralize count \"child\" \"children\"}}", r#"{"count": 3}"#).unwrap(), "children");
+        let forms = "{{pluralize count \"товар\" \"товара\" \"товаров\"}}";
+        assert_eq!(render(forms, r#"{"count": 21}"#).unwrap(), "товар");
+        assert_eq!(render(forms, r#"{"count": 3}"#).unwrap(), "товара");
+        assert_eq!(render(forms, r#"{"count": 12}"#).unwrap(), "товаров");
+    }
+
+    #[test]
+    fn test_uppercase() {
+        assert_eq!(render("{{uppercase state}}", r#"{"state": "paid"}"#).unwrap(), "PAID");
+    }
+}
@@ -0,0 +1,317 @@
+//! Handlebars registry shared by all renders. It holds partials stored in the database
+//! and compiled templates, and is built on the first render after a template or a partial changes.
+//! Changes made by other instances of the service are noticed by the content version of the tables.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use failure::Error as FailureError;
+use handlebars::{no_escape, Handlebars};
+use serde::Serialize;
+
+use models::{ContentVersion, RenderedTemplate, Template, TemplatePartial};
+use repos::{TemplatePartialsRepo, TemplatesRepo};
+use services::plain_text::html_to_text;
+use services::template_helpers::register_helpers;
+
+/// Builds compiled templates once and keeps them until `invalidate` is called
+/// or templates or partials are changed in the database
+pub struct TemplateRegistry {
+    strict_mode: bool,
+    state: Mutex<RegistryState>,
+}
+
+#[derive(Default)]
+struct RegistryState {
+    /// Bumped on every invalidation, so that a registry built from outdated rows is not kept
+    generation: u64,
+    compiled: Option<(Versions, Arc<CompiledTemplates>)>,
+}
+
+/// Content versions of templates and partials the registry was built from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Versions {
+    templates: ContentVersion,
+    partials: ContentVersion,
+}
+
+impl TemplateRegistry {
+    /// With `strict_mode` rendering fails on variables missing in the email data
+    pub fn new(strict_mode: bool) -> Self {
+        Self {
+            strict_mode,
+            state: Mutex::new(RegistryState::default()),
+        }
+    }
+
+    /// Compiled templates, loaded from the repos if the registry was invalidated
+    /// or templates or partials have changed since it was built
+    pub fn get_or_build(
+        &self,
+        templates_repo: &TemplatesRepo,
+        partials_repo: &TemplatePartialsRepo,
+    ) -> Result<Arc<CompiledTemplates>, FailureError> {
+        let versions = Versions {
+            templates: templates_repo.content_version()?,
+            partials: partials_repo.content_version()?,
+        };
+        let generation = {
+            let state = self.state.lock().unwrap();
+            if let Some((ref built_from, ref compiled)) = state.compiled {
+                if *built_from == versions {
+                    return Ok(compiled.clone());
+                }
+            }
+            state.generation
+        };
+
+        let templates = templates_repo.list()?;
+        let partials = partials_repo.list()?;
+        let compiled = Arc::new(CompiledTemplates::new(&templates, &partials, self.strict_mode));
+
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            state.compiled = Some((versions, compiled.clone()));
+        }
+        Ok(compiled)
+    }
+
+    /// Drop compiled templates, call it after a template or a partial is changed
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.compiled = None;
+    }
+}
+
+/// Templates and partials registered in Handlebars
+pub struct CompiledTemplates {
+    html: Handlebars,
+    /// Subjects and plain text parts mustn't be HTML-escaped
+    text: Handlebars,
+    /// Sources of the registered templates by their keys
+    sources: HashMap<String, String>,
+}
+
+impl CompiledTemplates {
+    pub fn new(templates: &[Template], partials: &[TemplatePartial], strict_mode: bool) -> Self {
+        let mut html = Handlebars::new();
+        html.set_strict_mode(strict_mode);
+        register_helpers(&mut html);
+        let mut text = Handlebars::new();
+        text.register_escape_fn(no_escape);
+        text.set_strict_mode(strict_mode);
+        register_helpers(&mut text);
+
+        for partial in partials {
+            for handlebars in vec![&mut html, &mut text] {
+                if let Err(e) = handlebars.register_partial(&partial.name, &partial.data) {
+                    error!("Couldn't register template partial {}: {}", partial.name, e);
+                }
+            }
+        }
+
+        let mut compiled = Self {
+            html,
+            text,
+            sources: HashMap::new(),
+        };
+        for template in templates {
+            compiled.register(template_key(template, "html"), &template.data, false);
+            if let Some(ref subject) = template.subject {
+                compiled.register(template_key(template, "subject"), subject, true);
+            }
+            if let Some(ref plain_text) = template.plain_text {
+                compiled.register(template_key(template, "plain_text"), plain_text, true);
+            }
+        }
+        compiled
+    }
+
+    fn register(&mut self, key: String, source: &str, is_text: bool) {
+        let result = if is_text {
+            self.text.register_template_string(&key, source)
+        } else {
+            self.html.register_template_string(&key, source)
+        };
+        match result {
+            Ok(_) => {
+                self.sources.insert(key, source.to_string());
+            }
+            Err(e) => error!("Couldn't register template {}: {}", key, e),
+        }
+    }
+
+    /// Renders subject, HTML and plain text parts of the template with the email data.
+    /// Parts that differ from the registered ones, e.g. drafts, are compiled on the fly.
+    pub fn render<S: Serialize>(&self, template: &Template, data: &S) -> Result<RenderedTemplate, FailureError> {
+        let html = self
+            .render_part(&self.html, template_key(template, "html"), &template.data, data)
+            .map_err(|e| e.context(format!("Couldn't render template {} in locale {}", template.name, template.locale)))?;
+        let plain_text = match template.plain_text {
+            Some(ref plain_text) => self
+                .render_part(&self.text, template_key(template, "plain_text"), plain_text, data)
+                .map_err(|e| {
+                    e.context(format!(
+                        "Couldn't render plain text of template {} in locale {}",
+                        template.name, template.locale
+                    ))
+                })?,
+            None => html_to_text(&html),
+        };
+        let subject = match template.subject {
+            Some(ref subject) => Some(
+                self.render_part(&self.text, template_key(template, "subject"), subject, data)
+                    .map_err(|e| {
+                        e.context(format!(
+                            "Couldn't render subject of template {} in locale {}",
+                            template.name, template.locale
+                        ))
+                    })?,
+            ),
+            None => None,
+        };
+
+        Ok(RenderedTemplate { subject, html, plain_text })
+    }
+
+    fn render_part<S: Serialize>(&self, handlebars: &Handlebars, key: String, source: &str, data: &S) -> Result<String, FailureError> {
+        if self.sources.get(&key).map(|registered| registered == source).unwrap_or(false) {
+            handlebars.render(&key, data).map_err(From::from)
+        } else {
+            handlebars.render_template(source, data).map_err(From::from)
+        }
+    }
+}
+
+fn template_key(template: &Template, part: &str) -> String {
+    format!("{}/{}/{}", template.name, template.locale, part)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::SystemTime;
+
+    use super::*;
+    use repos::repo_factory::tests::{TemplatePartialsRepoMock, TemplatesRepoMock};
+
+    fn template(data: &str, subject: Option<&str>) -> Template {
+        Template {
+            id: 1,
+            name: "order_create_for_user".to_string(),
+            data: data.to_string(),
+            locale: "en".to_string(),
+            subject: subject.map(|subject| subject.to_string()),
+            plain_text: None,
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    fn layout() -> TemplatePartial {
+        TemplatePartial {
+            id: 1,
+            name: "layout".to_string(),
+            data: "<html><head><title>{{#> title}}Storiqa{{/title}}</title></head><body>{{> @partial-block}}</body></html>".to_string(),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    fn data() -> HashMap<&'static str, String> {
+        let mut data = HashMap::new();
+        data.insert("order_slug", "1000".to_string());
+        data
+    }
+
+    #[test]
+    fn test_render_with_layout() {
+        let body = "{{#> layout}}{{#*inline \"title\"}}Order{{/inline}}Order {{order_slug}}{{/layout}}";
+        let compiled = CompiledTemplates::new(&[template(body, Some("Order {{order_slug}} & co"))], &[layout()], false);
+
+        let rendered = compiled
+            .render(&template(body, Some("Order {{order_slug}} & co")), &data())
+            .unwrap();
+        assert_eq!(
+            rendered.html,
+            "<html><head><title>Order</title></head><body>Order 1000</body></html>"
+        );
+        assert_eq!(rendered.subject, Some("Order 1000 & co".to_string()));
+    }
+
+    #[test]
+    fn test_render_draft_with_layout() {
+        let compiled = CompiledTemplates::new(&[template("Order {{order_slug}}", None)], &[layout()], false);
+
+        let draft = template("{{#> layout}}Draft {{order_slug}}{{/layout}}", None);
+        assert_eq!(
+            compiled.render(&draft, &data()).unwrap().html,
+            "<html><head><title>Storiqa</title></head><body>Draft 1000</body></html>"
+        );
+    }
+
+    #[test]
+    fn test_render_with_helpers() {
+        let compiled = CompiledTemplates::new(&[], &[], false);
+        let rendered = compiled
+            .render(
+                &template("{{uppercase order_slug}}", Some("Order {{uppercase order_slug}}")),
+                &data(),
+            )
+            .unwrap();
+        assert_eq!(rendered.html, "1000");
+        assert_eq!(rendered.subject, Some("Order 1000".to_string()));
+    }
+
+    #[test]
+    fn test_strict_mode_fails_on_missing_variables() {
+        let body = "Order {{order_slug}} for {{user.first_name}}";
+        let lenient = CompiledTemplates::new(&[template(body, None)], &[], false);
+        assert_eq!(lenient.render(&template(body, None), &data()).unwrap().html, "Order 1000 for ");
+
+        let strict = CompiledTemplates::new(&[template(body, None)], &[], true);
+        assert!(strict.render(&template(body, None), &data()).is_err());
+    }
+
+    #[test]
+    fn test_invalidate_drops_compiled_templates() {
+        let registry = TemplateRegistry::new(false);
+        let versions = Versions {
+            templates: ContentVersion::default(),
+            partials: ContentVersion::default(),
+        };
+        registry.state.lock().unwrap().compiled = Some((versions, Arc::new(CompiledTemplates::new(&[], &[], false))));
+        registry.invalidate();
+        let state = registry.state.lock().unwrap();
+        assert!(state.compiled.is_none());
+        assert_eq!(state.generation, 1);
+    }
+
+    #[test]
+    fn test_rebuilds_when_content_version_changes() {
+        let registry = TemplateRegistry::new(false);
+        let current = Versions {
+            templates: ContentVersion::default(),
+            partials: ContentVersion::default(),
+        };
+        let outdated = Versions {
+            templates: ContentVersion {
+                count: 1,
+                updated_at: Some(SystemTime::now()),
+            },
+            ..current
+        };
+
+        let cached = Arc::new(CompiledTemplates::new(&[], &[], false));
+        registry.state.lock().unwrap().compiled = Some((current, cached.clone()));
+        let compiled = registry.get_or_build(&TemplatesRepoMock, &TemplatePartialsRepoMock).unwrap();
+        assert!(Arc::ptr_eq(&compiled, &cached));
+
+        registry.state.lock().unwrap().compiled = Some((outdated, cached.clone()));
+        let compiled = registry.get_or_build(&TemplatesRepoMock, &TemplatePartialsRepoMock).unwrap();
+        assert!(!Arc::ptr_eq(&compiled, &cached));
+        assert_eq!(
+            registry.state.lock().unwrap().compiled.as_ref().map(|&(versions, _)| versions),
+            Some(current)
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! Checks templates before they are saved: Handlebars syntax and variables
+//! referenced by the template against the fields of the variant's payload.
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use handlebars::Template as HandlebarsTemplate;
+use regex::Regex;
+use validator::{ValidationError, ValidationErrors};
+
+use stq_static_resources::{
+    ApplyEmailVerificationForUser, ApplyPasswordResetForUser, BaseProductModerationStatusForModerator, BaseProductModerationStatusForUser,
+    EmailVerificationForUser, OrderCreateForStore, OrderCreateForUser, OrderUpdateStateForStore, OrderUpdateStateForUser,
+    PasswordResetForUser, StoreModerationStatusForModerator, StoreModerationStatusForUser, TemplateVariant,
+};
+
+use models::NotificationCategory;
+use services::payload_fields::field_paths;
+
+/// Fields added by the service to the data of emails recipients can opt out of
+const OPTIONAL_EMAIL_FIELDS: &[&str] = &["unsubscribe_url"];
+
+lazy_static! {
+    static ref EXPRESSION: Regex = Regex::new(r"(?s)\{\{\{?~?(.*?)~?\}?\}\}").unwrap();
+}
+
+/// Variables available in templates of the variant, `None` if the payload is unknown
+/// or the template is a custom one sent with arbitrary data
+fn template_variables(template: &TemplateVariant) -> Option<Vec<String>> {
+    let mut variables = match *template {
+        TemplateVariant::OrderUpdateStateForUser => field_paths::<OrderUpdateStateForUser>(),
+        TemplateVariant::OrderUpdateStateForStore => field_paths::<OrderUpdateStateForStore>(),
+        TemplateVariant::OrderCreateForUser => field_paths::<OrderCreateForUser>(),
+        TemplateVariant::OrderCreateForStore => field_paths::<OrderCreateForStore>(),
+        TemplateVariant::EmailVerificationForUser | TemplateVariant::WalletEmailVerificationForUser => {
+            field_paths::<EmailVerificationForUser>()
+        }
+        TemplateVariant::PasswordResetForUser | TemplateVariant::WalletPasswordResetForUser => field_paths::<PasswordResetForUser>(),
+        TemplateVariant::ApplyEmailVerificationForUser | TemplateVariant::WalletApplyEmailVerificationForUser => {
+            field_paths::<ApplyEmailVerificationForUser>()
+        }
+        TemplateVariant::ApplyPasswordResetForUser | TemplateVariant::WalletApplyPasswordResetForUser => {
+            field_paths::<ApplyPasswordResetForUser>()
+        }
+        TemplateVariant::StoreModerationStatusForUser => field_paths::<StoreModerationStatusForUser>(),
+        TemplateVariant::BaseProductModerationStatusForUser => field_paths::<BaseProductModerationStatusForUser>(),
+        TemplateVariant::StoreModerationStatusForModerator => field_paths::<StoreModerationStatusForModerator>(),
+        TemplateVariant::BaseProductModerationStatusForModerator => field_paths::<BaseProductModerationStatusForModerator>(),
+        _ => return None,
+    };
+    if NotificationCategory::of_template(&template.to_string()).is_optional() {
+        variables.extend(OPTIONAL_EMAIL_FIELDS.iter().map(|field| field.to_string()));
+    }
+    Some(variables)
+}
+
+/// Validates body, subject and plain text parts of the template, errors are keyed by the part name
+pub fn validate_template(
+    template_name: &str,
+    data: Option<&str>,
+    subject: Option<&str>,
+    plain_text: Option<&str>,
+) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    let parts = vec![("data", data), ("subject", subject), ("plain_text", plain_text)];
+    for (field, text) in parts {
+        if let Some(text) = text {
+            for error in validate_part(template_name, text) {
+                errors.add(field, error);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Validates syntax of the partial, its variables are checked in templates using it
+pub fn validate_partial(data: &str) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if let Some(error) = syntax_error(data) {
+        errors.add("data", error);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn syntax_error(text: &str) -> Option<ValidationError> {
+    HandlebarsTemplate::compile(text).err().map(|e| {
+        let mut error = ValidationError::new("syntax");
+        error.message = Some(Cow::from(e.to_string()));
+        if let Some(line) = e.line_no {
+            error.add_param(Cow::from("line"), &line);
+        }
+        if let Some(column) = e.column_no {
+            error.add_param(Cow::from("column"), &column);
+        }
+        error
+    })
+}
+
+fn validate_part(template_name: &str, text: &str) -> Vec<ValidationError> {
+    if let Some(error) = syntax_error(text) {
+        return vec![error];
+    }
+
+    let variables = match TemplateVariant::from_str(template_name)
+        .ok()
+        .and_then(|template| template_variables(&template))
+    {
+        Some(variables) => variables,
+        None => return vec![],
+    };
+
+    let mut errors = vec![];
+    for variable in referenced_variables(text) {
+        let known = variables
+            .iter()
+            .any(|known| *known == variable || known.starts_with(&format!("{}.", variable)));
+        if !known {
+            let mut error = ValidationError::new("unknown_variable");
+            error.message = Some(Cow::from(format!("Template {} has no variable {}", template_name, variable)));
+            error.add_param(Cow::from("variable"), &variable);
+            errors.push(error);
+        }
+    }
+    errors
+}
+
+/// Paths of the variables referenced from the root context. Variables inside
+/// `each`/`with` blocks are relative to the block context and are skipped.
+fn referenced_variables(text: &str) -> Vec<String> {
+    let mut scopes: Vec<bool> = vec![];
+    let mut variables: Vec<String> = vec![];
+
+    for caps in EXPRESSION.captures_iter(text) {
+        let inner = caps[1].trim();
+        let (kind, body) = match inner.chars().next() {
+            Some(c) if "!/>#^&".contains(c) => (Some(c), inner[1..].trim()),
+            _ => (None, inner),
+        };
+        let mut tokens = tokenize(body);
+        if let Some(position) = tokens.iter().position(|token| token == "as") {
+            // block params, e.g. `{{#each items as |item|}}`
+            tokens.truncate(position);
+        }
+
+        let in_scope = scopes.iter().any(|scope| *scope);
+        let mut paths: Vec<&str> = vec![];
+        match kind {
+            Some('!') | Some('>') => continue,
+            Some('/') => {
+                scopes.pop();
+                continue;
+            }
+            Some('#') | Some('^') => {
+                if body.starts_with('>') || body.starts_with('*') {
+                    // partial blocks, e.g. `{{#> layout}}`, and inline partials
+                    scopes.push(false);
+                    continue;
+                }
+                if tokens.is_empty() {
+                    continue;
+                }
+                let helper = tokens[0].as_str();
+                let changes_scope = match helper {
+                    "if" | "unless" => false,
+                    "each" | "with" => true,
+                    _ => tokens.len() == 1,
+                };
+                if tokens.len() == 1 && changes_scope {
+                    paths.push(helper);
+                }
+                paths.extend(tokens[1..].iter().map(|token| token.as_str()));
+                scopes.push(changes_scope);
+            }
+            _ => {
+                if tokens.is_empty() || tokens[0] == "else" {
+                    continue;
+                }
+                if tokens.len() == 1 {
+                    paths.push(tokens[0].as_str());
+                } else {
+                    paths.extend(tokens[1..].iter().map(|token| token.as_str()));
+                }
+            }
+        }
+
+        for path in paths {
+            add_root_paths(path, in_scope, &mut variables);
+        }
+    }
+    variables
+}
+
+/// Adds root paths of the token, arguments of helper subexpressions like
+/// `(default title "Order")` are checked as well
+fn add_root_paths(token: &str, in_scope: bool, variables: &mut Vec<String>) {
+    let value = hash_value(token);
+    if value.starts_with('(') && value.ends_with(')') {
+        for argument in tokenize(&value[1..value.len() - 1]).iter().skip(1) {
+            add_root_paths(argument, in_scope, variables);
+        }
+    } else if let Some(path) = root_path(value, in_scope) {
+        if !variables.contains(&path) {
+            variables.push(path);
+        }
+    }
+}
+
+/// Value of the hash argument, e.g. `user.locale` of `locale=user.locale`
+fn hash_value(token: &str) -> &str {
+    match token.find('=') {
+        Some(position) if !token.starts_with('"') && !token.starts_with('\'') && !token.starts_with('(') => &token[position + 1..],
+        _ => token,
+    }
+}
+
+/// Path relative to the root context, `None` for literals, data variables and relative paths
+fn root_path(token: &str, in_scope: bool) -> Option<String> {
+    let is_literal = token.starts_with('"')
+        || token.starts_with('\'')
+        || token.parse::<f64>().is_ok()
+        || ["true", "false", "null", "undefined", "this", "."].contains(&token);
+    if is_literal || token.starts_with("../") {
+        return None;
+    }
+    if token.starts_with("@root.") {
+        return Some(token["@root.".len()..].to_string());
+    }
+    if token.starts_with('@') || in_scope {
+        return None;
+    }
+    let token = token.trim_left_matches("this.").trim_left_matches("./");
+    Some(token.to_string())
+}
+
+/// Splits helper call into tokens, keeping quoted strings and subexpressions whole
+fn tokenize(body: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut depth = 0;
+    for c in body.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            (Some(_), c) => current.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (None, '(') => {
+                depth += 1;
+                current.push(c);
+            }
+            (None, ')') => {
+                depth -= 1;
+                current.push(c);
+            }
+            (None, c) if c.is_whitespace() && depth == 0 => {
+                if !current.is_empty() {
+                    tokens.push(current.clone());
+                    current.clear();
+                }
+            }
+            (None, c) => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_template_passes() {
+        let data = "Dear {{user.first_name}}, order {{order_slug}} is {{order_state}}. \
+                    {{#if user}}<a href=\"{{cluster_url}}/orders/{{order_slug}}\">Track</a>{{/if}}";
+        assert!(validate_template(&TemplateVariant::OrderUpdateStateForUser.to_string(), Some(data), None, None).is_ok());
+    }
+
+    #[test]
+    fn test_syntax_error_is_reported() {
+        let errors = validate_template(&TemplateVariant::OrderCreateForUser.to_string(), Some("{{#if user}}Hi"), None, None).unwrap_err();
+        assert_eq!(errors.inner()["data"][0].code, "syntax");
+    }
+
+    #[test]
+    fn test_unknown_variable_is_reported() {
+        let errors = validate_template(
+            &TemplateVariant::OrderCreateForStore.to_string(),
+            Some("Store {{store_idd}}"),
+            Some("Order {{order_slug}} for {{user.first_name}}"),
+            None,
+        )
+        .unwrap_err();
+        let errors = errors.inner();
+        assert_eq!(errors["data"][0].code, "unknown_variable");
+        assert_eq!(errors["data"][0].params["variable"], "store_idd");
+        assert_eq!(errors["subject"][0].params["variable"], "user.first_name");
+    }
+
+    #[test]
+    fn test_unsubscribe_url_is_only_available_in_optional_emails() {
+        let data = Some("<a href=\"{{unsubscribe_url}}\">Unsubscribe</a>");
+        assert!(validate_template(&TemplateVariant::StoreModerationStatusForUser.to_string(), data, None, None).is_ok());
+
+        let errors = validate_template(&TemplateVariant::PasswordResetForUser.to_string(), data, None, None).unwrap_err();
+        assert_eq!(errors.inner()["data"][0].params["variable"], "unsubscribe_url");
+        let errors = validate_template(&TemplateVariant::OrderCreateForUser.to_string(), data, None, None).unwrap_err();
+        assert_eq!(errors.inner()["data"][0].params["variable"], "unsubscribe_url");
+    }
+
+    #[test]
+    fn test_block_scoped_variables_are_skipped() {
+        let data = "{{#with user}}{{first_name}} {{@root.token}}{{/with}} {{! comment {{nope}} }}";
+        let variables = referenced_variables(data);
+        assert_eq!(variables, vec!["user".to_string(), "token".to_string()]);
+    }
+
+    #[test]
+    fn test_partials_are_skipped() {
+        let data = "{{#> layout}}{{#*inline \"title\"}}Order {{order_slug}}{{/inline}}{{> footer}}{{/layout}}";
+        assert_eq!(referenced_variables(data), vec!["order_slug".to_string()]);
+        assert!(validate_partial("{{#if user}}").is_err());
+    }
+
+    #[test]
+    fn test_helper_arguments_are_checked() {
+        let variables = referenced_variables("{{format_date created_at \"%d %m\" locale=user.locale}}");
+        assert_eq!(variables, vec!["created_at".to_string(), "user.locale".to_string()]);
+    }
+
+    #[test]
+    fn test_subexpression_arguments_are_checked() {
+        let variables = referenced_variables("{{format_date (default created_att updated_at) locale=(lookup user \"locale\")}}");
+        assert_eq!(
+            variables,
+            vec!["created_att".to_string(), "updated_at".to_string(), "user".to_string()]
+        );
+    }
+}
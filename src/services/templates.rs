@@ -1,19 +1,63 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
 use failure::Error as FailureError;
+use validator::{ValidationError, ValidationErrors};
 
 use diesel::connection::AnsiTransactionManager;
 use diesel::pg::Pg;
 use diesel::Connection;
 use r2d2::ManageConnection;
 
+use errors::{validation_details, Error};
+use models::{
+    normalize_locale, CreateTemplate, NewTemplate, NewTemplatePartial, RenderedTemplate, Template, TemplatePartial, TemplatePreview,
+    TemplateSummary, TemplateVersion,
+};
 use repos::ReposFactory;
+use services::template_validation::{validate_partial, validate_template};
 use services::types::{Service, ServiceFuture};
 use stq_static_resources::TemplateVariant;
 
 pub trait TemplatesService {
+    /// List templates in all locales, optionally only those with names containing `name_filter`
+    fn list_templates(self, name_filter: Option<String>) -> ServiceFuture<Vec<TemplateSummary>>;
     /// Get template by name
-    fn get_template_by_name(self, template_name: TemplateVariant) -> ServiceFuture<String>;
+    fn get_template_by_name(self, template_name: String) -> ServiceFuture<String>;
+    /// Get template by name in the locale
+    fn get_localized_template(self, template_name: String, locale: String) -> ServiceFuture<String>;
     // Update template by name
-    fn update_template(self, template_name: TemplateVariant, text: String) -> ServiceFuture<String>;
+    fn update_template(self, template_name: String, text: String) -> ServiceFuture<String>;
+    /// Update template in the locale, creates the translation if it doesn't exist yet
+    fn update_localized_template(self, template_name: String, locale: String, text: String) -> ServiceFuture<String>;
+    /// Create custom template, or a translation of an existing one
+    fn create_template(self, payload: CreateTemplate) -> ServiceFuture<Template>;
+    /// Delete custom template in all locales, templates with typed endpoints can't be deleted
+    fn delete_template(self, template_name: String) -> ServiceFuture<Vec<Template>>;
+    /// Get subject template, `None` means the subject given by the caller is sent
+    fn get_template_subject(self, template_name: String, locale: Option<String>) -> ServiceFuture<Option<String>>;
+    /// Update subject template, empty subject restores subjects given by callers
+    fn update_template_subject(self, template_name: String, locale: Option<String>, subject: String) -> ServiceFuture<Option<String>>;
+    /// Get plain text template, `None` means plain text is generated from HTML
+    fn get_template_plain_text(self, template_name: String, locale: Option<String>) -> ServiceFuture<Option<String>>;
+    /// Update plain text template, empty text restores generating it from HTML
+    fn update_template_plain_text(self, template_name: String, locale: Option<String>, text: String) -> ServiceFuture<Option<String>>;
+    /// Render the template, or its draft from the preview, without sending anything
+    fn preview_template(self, template_name: String, locale: Option<String>, preview: TemplatePreview) -> ServiceFuture<RenderedTemplate>;
+    /// List versions of the template, newest first
+    fn list_template_versions(self, template_name: String, locale: Option<String>) -> ServiceFuture<Vec<TemplateVersion>>;
+    /// Get specific version of the template
+    fn get_template_version(self, template_name: String, locale: Option<String>, version: i32) -> ServiceFuture<TemplateVersion>;
+    /// Restore content of the template from the version, the restored content becomes a new version
+    fn rollback_template(self, template_name: String, locale: Option<String>, version: i32) -> ServiceFuture<TemplateVersion>;
+    /// List partials shared by templates
+    fn list_template_partials(self) -> ServiceFuture<Vec<TemplatePartial>>;
+    /// Get partial by name
+    fn get_template_partial(self, name: String) -> ServiceFuture<TemplatePartial>;
+    /// Create partial or replace its content, templates using it are rendered with the new content
+    fn update_template_partial(self, name: String, data: String) -> ServiceFuture<TemplatePartial>;
+    /// Delete partial by name
+    fn delete_template_partial(self, name: String) -> ServiceFuture<TemplatePartial>;
 }
 
 impl<T, M, F> TemplatesService for Service<T, M, F>
@@ -22,32 +66,563 @@ where
     M: ManageConnection<Connection = T>,
     F: ReposFactory<T>,
 {
-    fn get_template_by_name(self, template_name: TemplateVariant) -> ServiceFuture<String> {
+    fn list_templates(self, name_filter: Option<String>) -> ServiceFuture<Vec<TemplateSummary>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        let name_filter = name_filter
+            .map(|name_filter| name_filter.trim().to_string())
+            .filter(|name_filter| !name_filter.is_empty());
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            templates_repo
+                .search(name_filter.as_ref().map(|name_filter| name_filter.as_str()))
+                .map(|templates| templates.iter().map(TemplateSummary::from).collect())
+                .map_err(|e: FailureError| e.context("Service MailService, list_templates endpoint error occurred.").into())
+        })
+    }
+
+    fn get_template_by_name(self, template_name: String) -> ServiceFuture<String> {
+        let default_locale = self.static_context.config.templates.default_locale.clone();
+        self.get_localized_template(template_name, default_locale)
+    }
+
+    fn get_localized_template(self, template_name: String, locale: String) -> ServiceFuture<String> {
         let repo_factory = self.static_context.repo_factory.clone();
         let user_id = self.dynamic_context.user_id;
+        let locale = normalize_locale(&locale);
 
         self.spawn_on_pool(move |conn| {
             let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
             templates_repo
-                .get_template_by_name(template_name)
+                .find_localized(&template_name, &[locale.clone()])
+                .and_then(|template| {
+                    template.ok_or_else(|| {
+                        format_err!("Template {} in locale {} not found", template_name, locale)
+                            .context(Error::NotFound)
+                            .into()
+                    })
+                })
                 .map(|template| template.data)
                 .map_err(|e: FailureError| {
-                    e.context("Service MailService, get_template_by_name endpoint error occurred.")
+                    e.context("Service MailService, get_localized_template endpoint error occurred.")
                         .into()
                 })
         })
     }
 
-    fn update_template(self, template_name: TemplateVariant, text: String) -> ServiceFuture<String> {
+    fn update_template(self, template_name: String, text: String) -> ServiceFuture<String> {
+        let default_locale = self.static_context.config.templates.default_locale.clone();
+        self.update_localized_template(template_name, default_locale, text)
+    }
+
+    fn update_localized_template(self, template_name: String, locale: String, text: String) -> ServiceFuture<String> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
+        let user_id = self.dynamic_context.user_id;
+        let locale = normalize_locale(&locale);
+        let default_locale = self.static_context.config.templates.default_locale.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            conn.transaction::<Template, FailureError, _>(move || {
+                validate_template(&template_name, Some(&text), None, None).map_err(|errors| {
+                    format_err!("Template {} is invalid: {}", template_name, validation_details(&errors)).context(Error::Parse)
+                })?;
+                let template = match templates_repo.find_localized(&template_name, &[locale.clone()])? {
+                    Some(_) => templates_repo.update(&template_name, &locale, text)?,
+                    None => {
+                        // only translations are added here, new templates are created with `create_template`
+                        templates_repo
+                            .find_localized(&template_name, &[default_locale])?
+                            .ok_or_else(|| format_err!("Template {} not found", template_name).context(Error::NotFound))?;
+                        templates_repo.create(NewTemplate {
+                            name: template_name,
+                            data: text,
+                            locale,
+                            subject: None,
+                            plain_text: None,
+                        })?
+                    }
+                };
+                templates_repo.create_version(&template, user_id)?;
+                Ok(template)
+            })
+            .map(|template| {
+                template_registry.invalidate();
+                template.data
+            })
+            .map_err(|e: FailureError| e.context("Service MailService, update_template endpoint error occurred.").into())
+        })
+    }
+
+    fn create_template(self, payload: CreateTemplate) -> ServiceFuture<Template> {
         let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
         let user_id = self.dynamic_context.user_id;
+        let CreateTemplate {
+            name,
+            data,
+            locale,
+            subject,
+            plain_text,
+        } = payload;
+        let locale = self.template_locale(locale);
+        let subject = subject
+            .map(|subject| subject.trim().to_string())
+            .filter(|subject| !subject.is_empty());
+        let plain_text = plain_text.filter(|text| !text.trim().is_empty());
 
         self.spawn_on_pool(move |conn| {
             let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            conn.transaction::<Template, FailureError, _>(move || {
+                let mut errors = match validate_template(
+                    &name,
+                    Some(&data),
+                    subject.as_ref().map(|s| s.as_str()),
+                    plain_text.as_ref().map(|s| s.as_str()),
+                ) {
+                    Ok(_) => ValidationErrors::new(),
+                    Err(errors) => errors,
+                };
+                if !is_valid_template_name(&name) {
+                    let mut error = ValidationError::new("name");
+                    error.message = Some(Cow::from("Template name may contain lowercase letters, digits, '-' and '_' only"));
+                    errors.add("name", error);
+                } else if templates_repo.find_localized(&name, &[locale.clone()])?.is_some() {
+                    let mut error = ValidationError::new("exists");
+                    error.message = Some(Cow::from(format!("Template {} already exists in locale {}", name, locale)));
+                    errors.add("name", error);
+                }
+                if !errors.is_empty() {
+                    return Err(format_err!("Template {} is invalid: {}", name, validation_details(&errors))
+                        .context(Error::Parse)
+                        .into());
+                }
+
+                let template = templates_repo.create(NewTemplate {
+                    name,
+                    data,
+                    locale,
+                    subject,
+                    plain_text,
+                })?;
+                templates_repo.create_version(&template, user_id)?;
+                Ok(template)
+            })
+            .map(|template| {
+                template_registry.invalidate();
+                template
+            })
+            .map_err(|e: FailureError| e.context("Service MailService, create_template endpoint error occurred.").into())
+        })
+    }
+
+    fn delete_template(self, template_name: String) -> ServiceFuture<Vec<Template>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
+        let user_id = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            if TemplateVariant::from_str(&template_name).is_ok() {
+                let mut errors = ValidationErrors::new();
+                let mut error = ValidationError::new("builtin");
+                error.message = Some(Cow::from(format!("Template {} is used by a typed send endpoint", template_name)));
+                errors.add("name", error);
+                return Err(
+                    format_err!("Template {} can't be deleted: {}", template_name, validation_details(&errors))
+                        .context(Error::Parse)
+                        .into(),
+                );
+            }
             templates_repo
-                .update(template_name, text)
-                .map(|template| template.data)
-                .map_err(|e: FailureError| e.context("Service MailService, update_template endpoint error occurred.").into())
+                .delete(&template_name)
+                .and_then(|deleted| {
+                    if deleted.is_empty() {
+                        Err(format_err!("Template {} not found", template_name).context(Error::NotFound).into())
+                    } else {
+                        Ok(deleted)
+                    }
+                })
+                .map(|deleted| {
+                    template_registry.invalidate();
+                    deleted
+                })
+                .map_err(|e: FailureError| e.context("Service MailService, delete_template endpoint error occurred.").into())
         })
     }
+
+    fn get_template_subject(self, template_name: String, locale: Option<String>) -> ServiceFuture<Option<String>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        let locale = self.template_locale(locale);
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            templates_repo
+                .find_localized(&template_name, &[locale.clone()])
+                .and_then(|template| {
+                    template.ok_or_else(|| {
+                        format_err!("Template {} in locale {} not found", template_name, locale)
+                            .context(Error::NotFound)
+                            .into()
+                    })
+                })
+                .map(|template| template.subject)
+                .map_err(|e: FailureError| {
+                    e.context("Service MailService, get_template_subject endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+
+    fn update_template_subject(self, template_name: String, locale: Option<String>, subject: String) -> ServiceFuture<Option<String>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
+        let user_id = self.dynamic_context.user_id;
+        let locale = self.template_locale(locale);
+        let subject = Some(subject.trim().to_string()).filter(|subject| !subject.is_empty());
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            conn.transaction::<Template, FailureError, _>(move || {
+                validate_template(&template_name, None, subject.as_ref().map(|subject| subject.as_str()), None).map_err(|errors| {
+                    format_err!("Subject of template {} is invalid: {}", template_name, validation_details(&errors)).context(Error::Parse)
+                })?;
+                let template = templates_repo.update_subject(&template_name, &locale, subject)?;
+                templates_repo.create_version(&template, user_id)?;
+                Ok(template)
+            })
+            .map(|template| {
+                template_registry.invalidate();
+                template.subject
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service MailService, update_template_subject endpoint error occurred.")
+                    .into()
+            })
+        })
+    }
+
+    fn get_template_plain_text(self, template_name: String, locale: Option<String>) -> ServiceFuture<Option<String>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        let locale = self.template_locale(locale);
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            templates_repo
+                .find_localized(&template_name, &[locale.clone()])
+                .and_then(|template| {
+                    template.ok_or_else(|| {
+                        format_err!("Template {} in locale {} not found", template_name, locale)
+                            .context(Error::NotFound)
+                            .into()
+                    })
+                })
+                .map(|template| template.plain_text)
+                .map_err(|e: FailureError| {
+                    e.context("Service MailService, get_template_plain_text endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+
+    fn update_template_plain_text(self, template_name: String, locale: Option<String>, text: String) -> ServiceFuture<Option<String>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
+        let user_id = self.dynamic_context.user_id;
+        let locale = self.template_locale(locale);
+        let text = Some(text).filter(|text| !text.trim().is_empty());
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            conn.transaction::<Template, FailureError, _>(move || {
+                validate_template(&template_name, None, None, text.as_ref().map(|text| text.as_str())).map_err(|errors| {
+                    format_err!(
+                        "Plain text of template {} is invalid: {}",
+                        template_name,
+                        validation_details(&errors)
+                    )
+                    .context(Error::Parse)
+                })?;
+                let template = templates_repo.update_plain_text(&template_name, &locale, text)?;
+                templates_repo.create_version(&template, user_id)?;
+                Ok(template)
+            })
+            .map(|template| {
+                template_registry.invalidate();
+                template.plain_text
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service MailService, update_template_plain_text endpoint error occurred.")
+                    .into()
+            })
+        })
+    }
+
+    fn preview_template(self, template_name: String, locale: Option<String>, preview: TemplatePreview) -> ServiceFuture<RenderedTemplate> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        let requested_locales = locale.into_iter().chain(self.dynamic_context.locales.clone()).collect::<Vec<_>>();
+        let locales = locale_fallbacks(&requested_locales, &self.static_context.config.templates.default_locale);
+        let template_registry = self.static_context.template_registry.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            let compiled = template_registry.get_or_build(
+                &*repo_factory.create_templates_repo_with_sys_acl(&*conn),
+                &*repo_factory.create_template_partials_repo_with_sys_acl(&*conn),
+            )?;
+            templates_repo
+                .find_localized(&template_name, &locales)
+                .and_then(|template| {
+                    template.ok_or_else(|| {
+                        format_err!("Template {} not found in locales {:?}", template_name, locales)
+                            .context(Error::NotFound)
+                            .into()
+                    })
+                })
+                .and_then(|mut template| {
+                    let TemplatePreview {
+                        context,
+                        data,
+                        subject,
+                        plain_text,
+                    } = preview;
+                    if let Some(data) = data {
+                        template.data = data;
+                    }
+                    if subject.is_some() {
+                        template.subject = subject;
+                    }
+                    if plain_text.is_some() {
+                        template.plain_text = plain_text;
+                    }
+                    compiled.render(&template, &context)
+                })
+                .map_err(|e: FailureError| e.context("Service MailService, preview_template endpoint error occurred.").into())
+        })
+    }
+
+    fn list_template_versions(self, template_name: String, locale: Option<String>) -> ServiceFuture<Vec<TemplateVersion>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        let locale = self.template_locale(locale);
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            templates_repo.list_versions(&template_name, &locale).map_err(|e: FailureError| {
+                e.context("Service MailService, list_template_versions endpoint error occurred.")
+                    .into()
+            })
+        })
+    }
+
+    fn get_template_version(self, template_name: String, locale: Option<String>, version: i32) -> ServiceFuture<TemplateVersion> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+        let locale = self.template_locale(locale);
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            templates_repo
+                .get_version(&template_name, &locale, version)
+                .and_then(|template_version| {
+                    template_version.ok_or_else(|| {
+                        format_err!("Version {} of template {} in locale {} not found", version, template_name, locale)
+                            .context(Error::NotFound)
+                            .into()
+                    })
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service MailService, get_template_version endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+
+    fn rollback_template(self, template_name: String, locale: Option<String>, version: i32) -> ServiceFuture<TemplateVersion> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
+        let user_id = self.dynamic_context.user_id;
+        let locale = self.template_locale(locale);
+
+        self.spawn_on_pool(move |conn| {
+            let templates_repo = repo_factory.create_templates_repo(&*conn, user_id);
+            conn.transaction::<TemplateVersion, FailureError, _>(move || {
+                let target = templates_repo.get_version(&template_name, &locale, version)?.ok_or_else(|| {
+                    format_err!("Version {} of template {} in locale {} not found", version, template_name, locale).context(Error::NotFound)
+                })?;
+                templates_repo.update(&template_name, &locale, target.data)?;
+                templates_repo.update_subject(&template_name, &locale, target.subject)?;
+                let template = templates_repo.update_plain_text(&template_name, &locale, target.plain_text)?;
+                let restored = templates_repo.create_version(&template, user_id)?;
+                info!(
+                    "Template {} in locale {} rolled back to version {} as version {}",
+                    template_name, locale, version, restored.version
+                );
+                Ok(restored)
+            })
+            .map(|restored| {
+                template_registry.invalidate();
+                restored
+            })
+            .map_err(|e: FailureError| e.context("Service MailService, rollback_template endpoint error occurred.").into())
+        })
+    }
+
+    fn list_template_partials(self) -> ServiceFuture<Vec<TemplatePartial>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let partials_repo = repo_factory.create_template_partials_repo(&*conn, user_id);
+            partials_repo.list().map_err(|e: FailureError| {
+                e.context("Service MailService, list_template_partials endpoint error occurred.")
+                    .into()
+            })
+        })
+    }
+
+    fn get_template_partial(self, name: String) -> ServiceFuture<TemplatePartial> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_id = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let partials_repo = repo_factory.create_template_partials_repo(&*conn, user_id);
+            partials_repo
+                .get(&name)
+                .and_then(|partial| {
+                    partial.ok_or_else(|| format_err!("Template partial {} not found", name).context(Error::NotFound).into())
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service MailService, get_template_partial endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+
+    fn update_template_partial(self, name: String, data: String) -> ServiceFuture<TemplatePartial> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
+        let user_id = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let partials_repo = repo_factory.create_template_partials_repo(&*conn, user_id);
+            validate_partial(&data)
+                .map_err(|errors| {
+                    format_err!("Template partial {} is invalid: {}", name, validation_details(&errors))
+                        .context(Error::Parse)
+                        .into()
+                })
+                .and_then(|_| partials_repo.upsert(NewTemplatePartial { name, data }))
+                .map(|partial| {
+                    template_registry.invalidate();
+                    partial
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service MailService, update_template_partial endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+
+    fn delete_template_partial(self, name: String) -> ServiceFuture<TemplatePartial> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let template_registry = self.static_context.template_registry.clone();
+        let user_id = self.dynamic_context.user_id;
+
+        self.spawn_on_pool(move |conn| {
+            let partials_repo = repo_factory.create_template_partials_repo(&*conn, user_id);
+            partials_repo
+                .delete(&name)
+                .and_then(|partial| {
+                    partial.ok_or_else(|| format_err!("Template partial {} not found", name).context(Error::NotFound).into())
+                })
+                .map(|partial| {
+                    template_registry.invalidate();
+                    partial
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service MailService, delete_template_partial endpoint error occurred.")
+                        .into()
+                })
+        })
+    }
+}
+
+impl<T, M, F> Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    /// Locale of the template being managed, the default one if not given
+    fn template_locale(&self, locale: Option<String>) -> String {
+        locale
+            .map(|locale| normalize_locale(&locale))
+            .unwrap_or_else(|| self.static_context.config.templates.default_locale.clone())
+    }
+}
+
+/// Names of custom templates are used in URLs, e.g. `/send/black-friday_2018`
+fn is_valid_template_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 100
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Locales to look the template up in, most preferred first. Each requested locale is
+/// followed by its language, e.g. `de-at` by `de`, and the default locale goes last.
+pub fn locale_fallbacks(requested: &[String], default_locale: &str) -> Vec<String> {
+    let mut locales: Vec<String> = vec![];
+    {
+        let mut push = |locale: String| {
+            if !locale.is_empty() && !locales.contains(&locale) {
+                locales.push(locale);
+            }
+        };
+        for locale in requested {
+            let locale = normalize_locale(locale);
+            let language = locale.split('-').next().unwrap_or("").to_string();
+            push(locale);
+            push(language);
+        }
+        push(normalize_locale(default_locale));
+    }
+    locales
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_fallbacks_end_with_default_locale() {
+        assert_eq!(locale_fallbacks(&[], "en"), vec!["en".to_string()]);
+        assert_eq!(
+            locale_fallbacks(&["de_AT".to_string(), "ru".to_string()], "en"),
+            vec!["de-at".to_string(), "de".to_string(), "ru".to_string(), "en".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_template_names() {
+        assert!(is_valid_template_name("black-friday_2018"));
+        assert!(!is_valid_template_name(""));
+        assert!(!is_valid_template_name("Black Friday"));
+        assert!(!is_valid_template_name("../order_create_for_user"));
+    }
+
+    #[test]
+    fn test_locale_fallbacks_skip_duplicates() {
+        assert_eq!(
+            locale_fallbacks(&["en-US".to_string(), "en".to_string()], "en"),
+            vec!["en-us".to_string(), "en".to_string()]
+        );
+    }
 }
@@ -0,0 +1,255 @@
+//! Ordered list of email transports. A message goes to the first available
+//! transport and falls over to the next one when delivery fails. A transport
+//! failing repeatedly is skipped until its cooldown expires.
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use failure::{Context, Error as FailureError};
+use futures::future;
+use futures::prelude::*;
+
+use config::MailProvider;
+use errors::Error;
+use models::SendGridPayload;
+use services::outbox::is_permanent_failure;
+use services::sendgrid::SendgridService;
+use services::types::ServiceFuture;
+
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn is_available(&self, now: Instant) -> bool {
+        self.open_until.map_or(true, |open_until| now >= open_until)
+    }
+
+    fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.open_until = None;
+    }
+
+    /// Returns `true` if the breaker has been opened by this failure
+    fn record_failure(&mut self, failure_threshold: u32, cooldown: Duration, now: Instant) -> bool {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= failure_threshold {
+            self.open_until = Some(now + cooldown);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Context of the error returned by the last provider the email was sent to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProviderFailure(pub MailProvider);
+
+impl fmt::Display for ProviderFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Email provider {} failed", self.0)
+    }
+}
+
+/// Provider which failed to deliver the email, `None` if none of them has been tried
+pub fn failed_provider(e: &FailureError) -> Option<MailProvider> {
+    e.iter_chain()
+        .filter_map(|cause| cause.downcast_ref::<Context<ProviderFailure>>())
+        .map(|context| context.get_context().0)
+        .next()
+}
+
+struct TransportLink {
+    provider: MailProvider,
+    transport: Arc<SendgridService>,
+    breaker: Mutex<CircuitBreaker>,
+}
+
+pub struct TransportChain {
+    links: Vec<Arc<TransportLink>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl TransportChain {
+    pub fn new(transports: Vec<(MailProvider, Arc<SendgridService>)>, failure_threshold: u32, cooldown: Duration) -> Self {
+        let links = transports
+            .into_iter()
+            .map(|(provider, transport)| {
+                Arc::new(TransportLink {
+                    provider,
+                    transport,
+                    breaker: Mutex::new(CircuitBreaker::default()),
+                })
+            })
+            .collect();
+        Self {
+            links,
+            failure_threshold,
+            cooldown,
+        }
+    }
+
+    /// Sends the email and returns the provider which delivered it
+    pub fn send(&self, payload: SendGridPayload) -> ServiceFuture<MailProvider> {
+        let now = Instant::now();
+        let available = self
+            .links
+            .iter()
+            .filter(|link| link.breaker.lock().unwrap().is_available(now))
+            .cloned()
+            .collect::<VecDeque<_>>();
+
+        if available.is_empty() {
+            return Box::new(future::err(
+                format_err!("All email providers are cooling down").context(Error::HttpClient).into(),
+            ));
+        }
+
+        send_with_failover(available, payload, self.failure_threshold, self.cooldown)
+    }
+}
+
+fn send_with_failover(
+    mut links: VecDeque<Arc<TransportLink>>,
+    payload: SendGridPayload,
+    failure_threshold: u32,
+    cooldown: Duration,
+) -> ServiceFuture<MailProvider> {
+    let link = match links.pop_front() {
+        Some(link) => link,
+        None => return Box::new(future::err(format_err!("No email providers configured").context(Error::HttpClient).into())),
+    };
+
+    Box::new(link.transport.send(payload.clone()).then(move |res| -> ServiceFuture<MailProvider> {
+        match res {
+            Ok(()) => {
+                link.breaker.lock().unwrap().record_success();
+                Box::new(future::ok(link.provider))
+            }
+            Err(e) => {
+                // only the message itself was rejected, other providers won't accept it either
+                if is_permanent_failure(&e) {
+                    return Box::new(future::err(e.context(ProviderFailure(link.provider)).into()));
+                }
+
+                let opened = link
+                    .breaker
+                    .lock()
+                    .unwrap()
+                    .record_failure(failure_threshold, cooldown, Instant::now());
+                if opened {
+                    error!("Email provider {} failed repeatedly, skipping it for {:?}", link.provider, cooldown);
+                }
+
+                if links.is_empty() {
+                    Box::new(future::err(e.context(ProviderFailure(link.provider)).into()))
+                } else {
+                    warn!("Email provider {} failed, falling over to the next one: {:?}", link.provider, e);
+                    send_with_failover(links, payload, failure_threshold, cooldown)
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use mime::TEXT_PLAIN;
+
+    use stq_static_resources::SimpleMail;
+
+    use super::*;
+    use services::mocks::sendgrid::SendgridServiceMock;
+
+    struct FailingTransport {
+        rejects: bool,
+    }
+
+    impl SendgridService for FailingTransport {
+        fn send(&self, _payload: SendGridPayload) -> ServiceFuture<()> {
+            let kind = if self.rejects { Error::ProviderRejected } else { Error::HttpClient };
+            Box::new(future::err(format_err!("Provider failed").context(kind).into()))
+        }
+    }
+
+    fn payload() -> SendGridPayload {
+        let mail = SimpleMail {
+            to: "user@storiqa.com".to_string(),
+            subject: "subject".to_string(),
+            text: "text".to_string(),
+        };
+        SendGridPayload::from_send_mail(mail, "from@storiqa.com".to_string(), "Storiqa".to_string(), TEXT_PLAIN)
+    }
+
+    #[test]
+    fn test_falls_over_to_next_provider() {
+        let chain = TransportChain::new(
+            vec![
+                (MailProvider::Sendgrid, Arc::new(FailingTransport { rejects: false }) as Arc<SendgridService>),
+                (MailProvider::Smtp, Arc::new(SendgridServiceMock) as Arc<SendgridService>),
+            ],
+            3,
+            Duration::from_secs(60),
+        );
+        assert_eq!(chain.send(payload()).wait().unwrap(), MailProvider::Smtp);
+    }
+
+    #[test]
+    fn test_does_not_fall_over_on_rejection() {
+        let chain = TransportChain::new(
+            vec![
+                (MailProvider::Sendgrid, Arc::new(FailingTransport { rejects: true }) as Arc<SendgridService>),
+                (MailProvider::Smtp, Arc::new(SendgridServiceMock) as Arc<SendgridService>),
+            ],
+            3,
+            Duration::from_secs(60),
+        );
+        let e = chain.send(payload()).wait().unwrap_err();
+        assert_eq!(failed_provider(&e), Some(MailProvider::Sendgrid));
+    }
+
+    #[test]
+    fn test_reports_last_failed_provider() {
+        let chain = TransportChain::new(
+            vec![
+                (MailProvider::Sendgrid, Arc::new(FailingTransport { rejects: false }) as Arc<SendgridService>),
+                (MailProvider::Smtp, Arc::new(FailingTransport { rejects: false }) as Arc<SendgridService>),
+            ],
+            3,
+            Duration::from_secs(60),
+        );
+        let e = chain.send(payload()).wait().unwrap_err();
+        assert_eq!(failed_provider(&e), Some(MailProvider::Smtp));
+        assert!(chain.links.iter().all(|link| link.breaker.lock().unwrap().consecutive_failures == 1));
+    }
+
+    #[test]
+    fn test_skips_provider_with_open_circuit() {
+        let chain = TransportChain::new(
+            vec![(MailProvider::Sendgrid, Arc::new(FailingTransport { rejects: false }) as Arc<SendgridService>)],
+            2,
+            Duration::from_secs(60),
+        );
+        for _ in 0..2 {
+            assert!(chain.send(payload()).wait().is_err());
+        }
+        assert!(!chain.links[0].breaker.lock().unwrap().is_available(Instant::now()));
+    }
+
+    #[test]
+    fn test_circuit_breaker_closes_after_cooldown() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+        assert!(!breaker.record_failure(2, Duration::from_secs(10), now));
+        assert!(breaker.record_failure(2, Duration::from_secs(10), now));
+        assert!(!breaker.is_available(now));
+        assert!(breaker.is_available(now + Duration::from_secs(10)));
+        breaker.record_success();
+        assert!(breaker.is_available(now));
+    }
+}
@@ -0,0 +1,155 @@
+//! Unsubscribe links. Token carries the address and the category of emails signed
+//! with the configured secret, so the opt-out is recorded without authorization.
+//! Opening the link only shows what it unsubscribes from, the opt-out is recorded
+//! by a POST, the way mail clients do it for one-click unsubscribe (RFC 8058).
+
+use base64;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use hmac::{Hmac, Mac};
+use r2d2::ManageConnection;
+use sha1::Sha1;
+
+use config::UnsubscribeConf;
+use errors::Error;
+use models::{normalize_email, NewNotificationPreference, NotificationCategory, NotificationPreference, UnsubscribeConfirmation};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+type HmacSha1 = Hmac<Sha1>;
+
+pub trait UnsubscribeService {
+    /// Address and category of emails the token unsubscribes from, nothing is changed
+    fn unsubscribe_confirmation(self, token: String) -> ServiceFuture<UnsubscribeConfirmation>;
+    /// Disable category of emails for the address from the token
+    fn unsubscribe(self, token: String) -> ServiceFuture<NotificationPreference>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > UnsubscribeService for Service<T, M, F>
+{
+    fn unsubscribe_confirmation(self, token: String) -> ServiceFuture<UnsubscribeConfirmation> {
+        let secret = self.static_context.config.unsubscribe.secret.clone();
+
+        Box::new(future::result(
+            parse_unsubscribe_token(&secret, &token)
+                .filter(|&(_, category)| category.is_optional())
+                .map(|(email, category)| UnsubscribeConfirmation { email, category })
+                .ok_or_else(|| {
+                    format_err!("Invalid unsubscribe token {}", token)
+                        .context(Error::Forbidden)
+                        .context("Service unsubscribe, unsubscribe_confirmation endpoint error occurred.")
+                        .into()
+                }),
+        ))
+    }
+
+    fn unsubscribe(self, token: String) -> ServiceFuture<NotificationPreference> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let secret = self.static_context.config.unsubscribe.secret.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let preferences_repo = repo_factory.create_notification_preferences_repo_with_sys_acl(&*conn);
+            parse_unsubscribe_token(&secret, &token)
+                .filter(|&(_, category)| category.is_optional())
+                .ok_or_else(|| format_err!("Invalid unsubscribe token {}", token).context(Error::Forbidden).into())
+                .and_then(|(email, category)| {
+                    info!("Unsubscribing {} from {} emails", email, category);
+                    preferences_repo.upsert(NewNotificationPreference {
+                        user_id: None,
+                        email,
+                        category,
+                        enabled: false,
+                    })
+                })
+                .map_err(|e: FailureError| e.context("Service unsubscribe, unsubscribe endpoint error occurred.").into())
+        })
+    }
+}
+
+/// Link to the unsubscribe endpoint for emails of the category sent to the address
+pub fn unsubscribe_url(conf: &UnsubscribeConf, email: &str, category: NotificationCategory) -> String {
+    format!(
+        "{}/unsubscribe/{}",
+        conf.url.trim_right_matches('/'),
+        unsubscribe_token(&conf.secret, email, category)
+    )
+}
+
+pub fn unsubscribe_token(secret: &str, email: &str, category: NotificationCategory) -> String {
+    let payload = format!("{}\n{}", normalize_email(email), category);
+    format!(
+        "{}.{}",
+        base64::encode_config(payload.as_bytes(), base64::URL_SAFE_NO_PAD),
+        base64::encode_config(&sign(secret, payload.as_bytes()), base64::URL_SAFE_NO_PAD)
+    )
+}
+
+/// Address and category of the token, `None` if the token is malformed or its signature doesn't match
+pub fn parse_unsubscribe_token(secret: &str, token: &str) -> Option<(String, NotificationCategory)> {
+    let mut parts = token.splitn(2, '.');
+    let payload = base64::decode_config(parts.next()?, base64::URL_SAFE_NO_PAD).ok()?;
+    let signature = base64::decode_config(parts.next()?, base64::URL_SAFE_NO_PAD).ok()?;
+
+    let mut mac = HmacSha1::new_varkey(secret.as_bytes()).ok()?;
+    mac.input(&payload);
+    mac.verify(&signature).ok()?;
+
+    let payload = String::from_utf8(payload).ok()?;
+    let mut fields = payload.splitn(2, '\n');
+    let email = fields.next()?.to_string();
+    let category = fields.next()?.parse().ok()?;
+    Some((email, category))
+}
+
+fn sign(secret: &str, payload: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha1::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.input(payload);
+    mac.result().code().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_round_trip() {
+        let token = unsubscribe_token("secret", " User@Example.com", NotificationCategory::Marketing);
+        assert_eq!(
+            parse_unsubscribe_token("secret", &token),
+            Some(("user@example.com".to_string(), NotificationCategory::Marketing))
+        );
+    }
+
+    #[test]
+    fn test_token_with_wrong_signature_is_rejected() {
+        let token = unsubscribe_token("secret", "user@example.com", NotificationCategory::Marketing);
+        assert_eq!(parse_unsubscribe_token("another secret", &token), None);
+
+        let forged = format!(
+            "{}.{}",
+            base64::encode_config(b"user@example.com\nsecurity", base64::URL_SAFE_NO_PAD),
+            token.split('.').nth(1).unwrap()
+        );
+        assert_eq!(parse_unsubscribe_token("secret", &forged), None);
+        assert_eq!(parse_unsubscribe_token("secret", "garbage"), None);
+    }
+
+    #[test]
+    fn test_unsubscribe_url() {
+        let conf = UnsubscribeConf {
+            url: "https://storiqa.com/api/notifications/".to_string(),
+            secret: "secret".to_string(),
+        };
+        let url = unsubscribe_url(&conf, "user@example.com", NotificationCategory::Moderation);
+        assert!(url.starts_with("https://storiqa.com/api/notifications/unsubscribe/"));
+    }
+}
@@ -0,0 +1,41 @@
+//! Client of the users microservice, it owns the accounts and their addresses
+use failure::Fail;
+use futures::Future;
+use hyper::header::Authorization;
+use hyper::{Headers, Method};
+
+use stq_http::client::ClientHandle;
+use stq_types::UserId;
+
+use config::UsersConf;
+use errors::Error;
+use models::UserAccount;
+use services::types::ServiceFuture;
+
+pub trait UsersClient: Send + Sync {
+    /// Account of the user, `None` if there is no such user
+    fn get_account(&self, user_id: UserId) -> ServiceFuture<Option<UserAccount>>;
+}
+
+#[derive(Clone)]
+pub struct UsersClientImpl {
+    pub config: UsersConf,
+    pub client_handle: ClientHandle,
+}
+
+impl UsersClient for UsersClientImpl {
+    fn get_account(&self, user_id: UserId) -> ServiceFuture<Option<UserAccount>> {
+        let url = format!("{}/users/{}", self.config.url, user_id);
+        debug!("UsersClient get_account: url=\"{}\"", url);
+
+        // the account is read on behalf of its owner
+        let mut headers = Headers::new();
+        headers.set(Authorization(user_id.to_string()));
+
+        Box::new(
+            self.client_handle
+                .request::<Option<UserAccount>>(Method::Get, url, None, Some(headers))
+                .map_err(|e| e.context(Error::HttpClient).into()),
+        )
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::{Duration, SystemTime};
+
+use diesel::pg::PgConnection;
+use diesel::Connection;
+use uuid::Uuid;
+
+use lib::config::Config;
+use lib::models::{EngagementSearch, NewNotification, NewNotificationEvent, NotificationStatus};
+use lib::repos::{NotificationsRepo, ReposFactory, ReposFactoryImpl, RolesCacheImpl};
+
+fn connection() -> PgConnection {
+    let config = Config::new().expect("Can't load app config!");
+    PgConnection::establish(&config.server.database).expect("Can't connect to the database")
+}
+
+// sent notifications of the template, one per list of the provider events recorded for it
+fn record_notifications(repo: &NotificationsRepo, template: &str, events: Vec<Vec<NotificationStatus>>) {
+    for statuses in events {
+        let id = Uuid::new_v4();
+        repo.create(NewNotification {
+            id,
+            recipient: "user@storiqa.com".to_string(),
+            template: Some(template.to_string()),
+            subject: "subject".to_string(),
+            user_id: None,
+        })
+        .unwrap();
+        repo.mark_sent(id, "sendgrid").unwrap();
+        for status in statuses {
+            repo.add_event(NewNotificationEvent {
+                notification_id: id,
+                status,
+                provider_event_id: Some(Uuid::new_v4().to_string()),
+                occurred_at: SystemTime::now(),
+            })
+            .unwrap();
+        }
+    }
+}
+
+// test engagement report counts clicks as opens
+#[test]
+fn test_engagement_report() {
+    let conn = connection();
+    conn.test_transaction::<_, (), _>(|| {
+        let repo_factory = ReposFactoryImpl::new(RolesCacheImpl::default());
+        let repo = repo_factory.create_notifications_repo_with_sys_acl(&conn);
+        let template = format!("engagement-{}", Uuid::new_v4());
+        record_notifications(
+            &*repo,
+            &template,
+            vec![
+                vec![NotificationStatus::Delivered, NotificationStatus::Opened],
+                vec![NotificationStatus::Delivered, NotificationStatus::Clicked],
+                vec![NotificationStatus::Delivered],
+                vec![],
+            ],
+        );
+
+        let report = repo
+            .engagement_report(EngagementSearch {
+                template: Some(template.clone()),
+                sent_from: None,
+                sent_to: None,
+            })
+            .unwrap();
+        assert_eq!(report.len(), 1);
+        let stats = &report[0];
+        assert_eq!(stats.template, template);
+        assert_eq!(stats.sent, 4);
+        assert_eq!(stats.delivered, 3);
+        assert_eq!(stats.opened, 2);
+        assert_eq!(stats.clicked, 1);
+        assert_eq!(stats.bounced, 0);
+        Ok(())
+    });
+}
+
+// test engagement report skips emails of other templates and outside of the period
+#[test]
+fn test_engagement_report_filters() {
+    let conn = connection();
+    conn.test_transaction::<_, (), _>(|| {
+        let repo_factory = ReposFactoryImpl::new(RolesCacheImpl::default());
+        let repo = repo_factory.create_notifications_repo_with_sys_acl(&conn);
+        let template = format!("engagement-{}", Uuid::new_v4());
+        let other_template = format!("engagement-{}", Uuid::new_v4());
+        record_notifications(&*repo, &template, vec![vec![NotificationStatus::Clicked]]);
+        record_notifications(&*repo, &other_template, vec![vec![NotificationStatus::Opened]]);
+
+        let report = repo
+            .engagement_report(EngagementSearch {
+                template: Some(template.clone()),
+                sent_from: None,
+                sent_to: None,
+            })
+            .unwrap();
+        assert_eq!(report.len(), 1);
+        assert_eq!((report[0].sent, report[0].opened, report[0].clicked), (1, 1, 1));
+
+        let report = repo
+            .engagement_report(EngagementSearch {
+                template: Some(template),
+                sent_from: None,
+                sent_to: Some(SystemTime::now() - Duration::from_secs(86400)),
+            })
+            .unwrap();
+        assert!(report.is_empty());
+        Ok(())
+    });
+}
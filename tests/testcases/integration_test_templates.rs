@@ -2,6 +2,8 @@ use hyper::Method;
 
 use std::result;
 
+use serde_json::Value;
+
 use tokio_core::reactor::Core;
 
 use stq_http::client::{self, ClientHandle as HttpClientHandle};
@@ -35,6 +37,16 @@ impl RpcClient {
             user,
         ))
     }
+
+    fn list_templates(&mut self, query: &str) -> result::Result<Value, client::Error> {
+        let user = self.user.map_or(None, |u| Some(u.to_string()));
+        self.core.run(self.http_client.request_with_auth_header::<Value>(
+            Method::Get,
+            format!("{}/templates{}", self.base_url, query),
+            None,
+            user,
+        ))
+    }
 }
 
 fn init_templates() -> Vec<TemplateVariant> {
@@ -94,6 +106,45 @@ fn test_get_template_unauthorized() {
     }
 }
 
+// test list templates by superuser
+#[test]
+fn test_list_templates_superuser() {
+    let user_id = UserId(1);
+    let base_url = super::common::setup();
+
+    let mut rpc = RpcClient::new(base_url.clone(), Some(user_id));
+    let templates_result = rpc.list_templates("");
+    println!("{:?}", templates_result);
+    let templates = templates_result.unwrap();
+    for template in init_templates().iter() {
+        let name = template.to_string();
+        assert!(templates
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|listed| listed["name"].as_str() == Some(name.as_str())));
+    }
+
+    let filtered = rpc.list_templates("?name=order_create").unwrap();
+    assert!(filtered
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|listed| listed["name"].as_str().unwrap().contains("order_create")));
+}
+
+// test list templates by regular user
+#[test]
+fn test_list_templates_regular_user() {
+    let user_id = UserId(123);
+    let base_url = super::common::setup();
+
+    let mut rpc = RpcClient::new(base_url.clone(), Some(user_id));
+    let templates_result = rpc.list_templates("");
+    println!("{:?}", templates_result);
+    assert!(templates_result.is_err());
+}
+
 fn create_template_mock() -> String {
     "<html>{{param1}}</html>".to_string()
 }
@@ -1,3 +1,4 @@
+extern crate diesel;
 extern crate futures;
 extern crate hyper;
 extern crate notifications_lib as lib;
@@ -6,7 +7,9 @@ extern crate stq_http;
 extern crate stq_static_resources;
 extern crate stq_types;
 extern crate tokio_core;
+extern crate uuid;
 
 mod common;
 
+mod integration_test_engagement;
 mod integration_test_templates;